@@ -1,83 +1,1026 @@
 mod persistence;
 mod resp;
 mod store;
+#[cfg(test)]
+mod testutil;
 
 mod prelude {
     pub use crate::persistence::*;
     pub use crate::resp::*;
     pub use crate::store::*;
-    pub use clap::Parser;
-    pub use once_cell::sync::Lazy;
+    pub use clap::{CommandFactory, FromArgMatches, Parser};
+    pub use once_cell::sync::{Lazy, OnceCell};
     pub use std::{
-        collections::HashMap,
-        env,
+        cell::RefCell,
+        collections::{BTreeMap, HashMap},
         fs::File,
-        io::{BufReader, BufWriter, prelude::*},
+        io::{BufReader, BufWriter, Seek, prelude::*},
         net::{TcpListener, TcpStream},
-        process,
-        sync::{Arc, Mutex, RwLock},
+        ops::Bound,
+        path::Path,
+        sync::{
+            Arc, Condvar, Mutex, PoisonError, RwLock,
+            atomic::{AtomicBool, AtomicU64, Ordering},
+        },
         thread,
-        time::Duration,
+        time::{Duration, Instant},
     };
 }
 
 use prelude::*;
 
+const PROTECTED_MODE_ERR: &str = "DENIED Redis is running in protected mode because protected \
+mode is enabled and no password is set. In this mode connections are only accepted from the \
+loopback interface. If you want to connect from external computers, restart the server with \
+'--protected-mode false' or bind it to a loopback address.";
+
+static PROTECTED_MODE: AtomicBool = AtomicBool::new(false);
+
+static REQUEST_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(0);
+
+static MAX_REQUEST_BYTES: AtomicU64 = AtomicU64::new(DEFAULT_MAX_REQUEST_BYTES);
+
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Args {
     #[arg(default_value = "0.0.0.0:6379")]
     addr: String,
 
-    #[arg(default_value = "/tmp/aof.log")]
-    aof_path: String,
+    /// Directory holding the persistence files
+    #[arg(long, default_value = "/tmp")]
+    dir: String,
+
+    /// Name of the append-only file inside --dir
+    #[arg(long, default_value = "aof.log")]
+    appendfilename: String,
+
+    /// Prefix each AOF record with a CRC32 that is verified on replay
+    #[arg(long)]
+    aof_checksum: bool,
+
+    /// Run purely in memory without an AOF
+    #[arg(long)]
+    no_persistence: bool,
+
+    /// Journal into a bounded in-memory buffer instead of a file (for benchmarks)
+    #[arg(long)]
+    aof_memory: bool,
+
+    /// Append a human-readable log of every command to this file
+    #[arg(long)]
+    request_log: Option<String>,
+
+    /// Largest string value accepted by write commands, in bytes (0 for no limit)
+    #[arg(long, default_value_t = 0)]
+    max_string_bytes: u64,
+
+    /// Longest key accepted by write commands, in bytes (0 for no limit)
+    #[arg(long, default_value_t = 1024 * 1024)]
+    max_key_bytes: u64,
+
+    /// Reject write commands while still serving reads
+    #[arg(long)]
+    read_only: bool,
+
+    /// Allow DEBUG subcommands that exist only to support tests
+    #[arg(long)]
+    enable_debug: bool,
+
+    /// Record accesses to keys matching --audit-pattern in this file
+    #[arg(long)]
+    audit_log: Option<String>,
+
+    /// Glob of sensitive keys to audit; may be given more than once
+    #[arg(long)]
+    audit_pattern: Vec<String>,
+
+    /// Refuse non-loopback clients when bound to a public address without a password
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    protected_mode: bool,
+
+    /// Require exact CRLF terminators on protocol lines
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    strict_crlf: bool,
+
+    /// Also accept a whole request on one space-separated line, e.g. `*2 $3 GET $1 k`
+    #[arg(long)]
+    lenient_resp: bool,
+
+    /// Set SO_REUSEADDR on the listening socket (turning it off is Linux only)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    reuse_addr: bool,
+
+    /// Set SO_REUSEPORT so several processes can listen on the same port (Linux only)
+    #[arg(long)]
+    reuse_port: bool,
+
+    /// Seconds a client may take to send one complete request, 0 to disable
+    #[arg(long, default_value_t = 30)]
+    request_timeout: u64,
+
+    /// Largest single request a client may send, in bytes
+    #[arg(long, default_value_t = DEFAULT_MAX_REQUEST_BYTES)]
+    max_request_bytes: u64,
+
+    /// Replay the given AOF into memory, print a summary and exit
+    #[arg(long, value_name = "AOF_PATH")]
+    replay_only: Option<String>,
+
+    /// Accept connections while the AOF is replayed, answering -LOADING meanwhile
+    #[arg(long)]
+    async_loading: bool,
+
+    /// Read settings from a redis.conf-style file; command-line flags take precedence
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
 }
 
 fn main() {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let _ = START_TIME.set(Instant::now());
 
-    let listener = TcpListener::bind(args.addr).unwrap();
+    let runtime_params = match args.config.clone() {
+        Some(path) => read_config_file(&path, &mut args, &matches).unwrap_or_else(|e| {
+            eprintln!("Failed to read config file {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => Vec::new(),
+    };
 
-    let aof = Arc::new(Mutex::new(
-        Aof::new(args.aof_path.as_str(), 1).expect("Failed to open AOF"),
-    ));
+    STRICT_CRLF.store(args.strict_crlf, Ordering::Relaxed);
+    LENIENT_RESP.store(args.lenient_resp, Ordering::Relaxed);
+    READ_ONLY.store(args.read_only, Ordering::Relaxed);
+    ENABLE_DEBUG.store(args.enable_debug, Ordering::Relaxed);
+    register_default_hooks();
+    MAX_STRING_BYTES.store(args.max_string_bytes, Ordering::Relaxed);
+    MAX_KEY_BYTES.store(args.max_key_bytes, Ordering::Relaxed);
+    for (name, value) in &runtime_params {
+        if let Err(e) = set_config_param(name, value) {
+            eprintln!("Invalid config value for '{}': {}", name, e);
+            std::process::exit(1);
+        }
+    }
 
-    let aof_clone = Arc::clone(&aof);
-    thread::spawn(move || {
-        loop {
-            thread::sleep(Duration::from_secs(1));
-            if let Ok(mut aof) = aof_clone.lock() {
-                if let Err(e) = aof.sync() {
-                    eprintln!("AOF sync failed: {}", e);
-                }
+    if let Some(path) = &args.replay_only {
+        match replay_only(path, args.aof_checksum) {
+            Ok(report) => print!("{}", report),
+            Err(e) => {
+                eprintln!("Replay of {} failed: {}", path, e);
+                std::process::exit(1);
             }
         }
-    });
+        return;
+    }
+
+    let listener = bind_listener(&args.addr, args.reuse_addr, args.reuse_port).unwrap();
+
+    let bound_to_loopback = listener.local_addr().unwrap().ip().is_loopback();
+    PROTECTED_MODE.store(args.protected_mode && !bound_to_loopback, Ordering::Relaxed);
+    REQUEST_TIMEOUT_SECS.store(args.request_timeout, Ordering::Relaxed);
+    MAX_REQUEST_BYTES.store(args.max_request_bytes, Ordering::Relaxed);
+
+    let dir = args.dir.clone();
+    let appendfilename = args.appendfilename.clone();
+    let persistence = !args.no_persistence;
+    let checksums = args.aof_checksum;
+    let in_memory = args.aof_memory;
+    let load = move || {
+        let (aof, source) =
+            load_persistence(&dir, &appendfilename, persistence, checksums, in_memory)
+                .expect("Failed to load persisted data");
+        STATS.reset();
+        DIRTY.store(0, Ordering::Relaxed);
+
+        match source {
+            StartupSource::Aof => println!("Loaded data from AOF {}/{}", dir, appendfilename),
+            StartupSource::Empty => println!("Starting with an empty dataset"),
+        }
+
+        if let Some(aof) = aof {
+            let aof = Arc::new(Mutex::new(aof));
+            set_active_aof(Arc::clone(&aof));
+
+            let sync_period = aof.lock().unwrap().sync_period();
+            supervise("aof-sync", move || {
+                loop {
+                    thread::sleep(sync_period);
+                    if let Err(e) = sync_aof(&aof) {
+                        eprintln!("AOF sync failed: {}", e);
+                    }
+                }
+            });
+        }
+        LOADING.store(false, Ordering::Relaxed);
+    };
+
+    if args.async_loading {
+        LOADING.store(true, Ordering::Relaxed);
+        // A failed load would leave clients on -LOADING forever, so it takes
+        // the server down just as it does when loading before accepting.
+        thread::spawn(|| {
+            if std::panic::catch_unwind(load).is_err() {
+                std::process::exit(1);
+            }
+        });
+    } else {
+        load();
+    }
+
+    if let Some(path) = &args.request_log {
+        open_request_log(path).expect("Failed to open request log");
 
-    aof.lock().unwrap().read().expect("Failed to replay AOF");
+        supervise("request-log-flush", || {
+            loop {
+                thread::sleep(Duration::from_secs(1));
+                if let Err(e) = flush_request_log() {
+                    eprintln!("Request log flush failed: {}", e);
+                }
+            }
+        });
+    }
+
+    if let Some(path) = &args.audit_log {
+        open_audit_log(path, args.audit_pattern.clone()).expect("Failed to open audit log");
+
+        supervise("audit-log-flush", || {
+            loop {
+                thread::sleep(Duration::from_secs(1));
+                if let Err(e) = flush_audit_log() {
+                    eprintln!("Audit log flush failed: {}", e);
+                }
+            }
+        });
+    }
 
     for stream in listener.incoming() {
         let stream = stream.unwrap();
-        let _aof = Arc::clone(&aof);
 
         thread::spawn(|| {
-            let _ = handle_connection(stream, _aof);
+            let _ = handle_connection(stream);
         });
     }
 }
 
-fn handle_connection(stream: TcpStream, aof: Arc<Mutex<Aof>>) -> Result<(), std::io::Error> {
-    let mut buf_reader = BufReader::new(stream);
+// Maps a config file directive to the flag that overrides it.
+fn config_arg_id(directive: &str) -> String {
+    match directive {
+        "port" | "bind" => "addr".to_string(),
+        "appendonly" => "no_persistence".to_string(),
+        _ => directive.replace('-', "_"),
+    }
+}
+
+fn invalid_config(line: usize, msg: String) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, msg),
+    )
+}
+
+// Startup settings are written into args; runtime parameters are returned so
+// they can be applied through the same table CONFIG SET uses.
+fn read_config_file(
+    path: &str,
+    args: &mut Args,
+    matches: &clap::ArgMatches,
+) -> std::io::Result<Vec<(String, String)>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut runtime_params = Vec::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (directive, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let directive = directive.to_lowercase();
+        let value = value.trim().trim_matches('"');
+
+        let id = config_arg_id(&directive);
+        if matches.ids().any(|arg| arg == id.as_str())
+            && matches.value_source(&id) == Some(clap::parser::ValueSource::CommandLine)
+        {
+            continue;
+        }
+
+        let (host, port) = args
+            .addr
+            .rsplit_once(':')
+            .map(|(h, p)| (h.to_string(), p.to_string()))
+            .unwrap_or_else(|| (args.addr.clone(), "6379".to_string()));
+
+        match directive.as_str() {
+            "port" => {
+                let port: u16 = value
+                    .parse()
+                    .map_err(|_| invalid_config(i + 1, format!("invalid port '{}'", value)))?;
+                args.addr = format!("{}:{}", host, port);
+            }
+            "bind" => {
+                let host = value.split_whitespace().next().unwrap_or("0.0.0.0");
+                args.addr = if host.contains(':') {
+                    format!("[{}]:{}", host, port)
+                } else {
+                    format!("{}:{}", host, port)
+                };
+            }
+            "appendonly" => {
+                args.no_persistence = !parse_bool(value).map_err(|e| invalid_config(i + 1, e))?
+            }
+            "protected-mode" => {
+                args.protected_mode = parse_bool(value).map_err(|e| invalid_config(i + 1, e))?
+            }
+            "dir" => args.dir = value.to_string(),
+            "appendfilename" => args.appendfilename = value.to_string(),
+            "appendfsync" if value == "everysec" => {}
+            // Starting without the password a config asks for would leave the
+            // server open, so refuse until AUTH is supported.
+            "requirepass" => {
+                return Err(invalid_config(
+                    i + 1,
+                    "requirepass is not supported: this server has no AUTH".to_string(),
+                ));
+            }
+            "appendfsync" | "maxmemory" | "save" => {
+                eprintln!(
+                    "Ignoring unsupported config directive '{} {}'",
+                    directive, value
+                );
+            }
+            name if is_config_param(name) => {
+                runtime_params.push((name.to_string(), value.to_string()));
+            }
+            _ => {
+                return Err(invalid_config(
+                    i + 1,
+                    format!("unknown directive '{}'", directive),
+                ));
+            }
+        }
+    }
+
+    Ok(runtime_params)
+}
+
+// Replays into the in-memory store and returns the summary to print.
+fn replay_only(path: &str, checksums: bool) -> std::io::Result<String> {
+    let path = Path::new(path);
+    if !path.is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "AOF file not found",
+        ));
+    }
+    let dir = path.parent().and_then(|p| p.to_str()).unwrap_or(".");
+    let dir = if dir.is_empty() { "." } else { dir };
+    let filename = path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .unwrap_or_default();
+
+    let summary = Aof::new(dir, filename, 1)?
+        .with_checksums(checksums)
+        .with_repair(false)
+        .read()?;
+
+    let mut names: Vec<_> = summary.commands.iter().collect();
+    names.sort();
+    let mut report = format!("Replay summary for {}\n", path.display());
+    for (name, count) in names {
+        report += &format!("  {}: {}\n", name, count);
+    }
+    report += &format!("  total: {}\n", summary.commands.values().sum::<u64>());
+    report += &format!("  errors: {}\n", summary.errors);
+    report += &format!("  keys: {}\n", read_cache().len());
+    match summary.torn_tail_at {
+        Some(offset) => report += &format!("  invalid tail at offset {}\n", offset),
+        None => report += "  tail: clean\n",
+    }
+    Ok(report)
+}
+
+// std's bind already sets SO_REUSEADDR on Unix, so a hand-built socket is
+// only needed to turn that off or to add SO_REUSEPORT.
+fn bind_listener(addr: &str, reuse_addr: bool, reuse_port: bool) -> std::io::Result<TcpListener> {
+    if reuse_addr && !reuse_port {
+        return TcpListener::bind(addr);
+    }
+    bind_with_options(addr, reuse_addr, reuse_port)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_with_options(
+    _addr: &str,
+    _reuse_addr: bool,
+    _reuse_port: bool,
+) -> std::io::Result<TcpListener> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "--reuse-port and --reuse-addr false are only supported on Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn set_socket_flag(fd: libc::c_int, option: libc::c_int, enabled: bool) -> std::io::Result<()> {
+    let value = enabled as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn bind_with_options(
+    addr: &str,
+    reuse_addr: bool,
+    reuse_port: bool,
+) -> std::io::Result<TcpListener> {
+    use std::net::{SocketAddr, ToSocketAddrs};
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "No address to bind")
+    })?;
+
+    let family = match addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let fd = unsafe { libc::socket(family, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    set_socket_flag(fd, libc::SO_REUSEADDR, reuse_addr)?;
+    if reuse_port {
+        set_socket_flag(fd, libc::SO_REUSEPORT, true)?;
+    }
+
+    let ret = match addr {
+        SocketAddr::V4(v4) => {
+            let sin = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: v4.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(v4.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            unsafe {
+                libc::bind(
+                    fd,
+                    &sin as *const libc::sockaddr_in as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                )
+            }
+        }
+        SocketAddr::V6(v6) => {
+            let sin6 = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: v6.port().to_be(),
+                sin6_flowinfo: v6.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: v6.ip().octets(),
+                },
+                sin6_scope_id: v6.scope_id(),
+            };
+            unsafe {
+                libc::bind(
+                    fd,
+                    &sin6 as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                )
+            }
+        }
+    };
+    if ret < 0 || unsafe { libc::listen(fd, 128) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(TcpListener::from(socket))
+}
+
+fn supervise<F>(name: &'static str, task: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let task = Arc::new(task);
+    thread::spawn(move || {
+        loop {
+            let task = Arc::clone(&task);
+            let worker = thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || task())
+                .expect("Failed to spawn background thread");
+
+            match worker.join() {
+                Ok(()) => break,
+                Err(_) => {
+                    eprintln!("Background thread '{}' panicked, restarting", name);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    });
+}
+
+// Bounds the total time spent reading one request, so a client that sends a
+// partial multibulk and then stalls cannot hold its thread indefinitely.
+struct BudgetedStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+}
+
+impl Read for BudgetedStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let timeout = match self.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "request timed out",
+                    ));
+                }
+                Some(remaining)
+            }
+            None => None,
+        };
+        self.stream.set_read_timeout(timeout)?;
+        self.stream.read(buf)
+    }
+}
+
+static INLINE_PING_COMMAND: Lazy<RespValue> =
+    Lazy::new(|| RespValue::Array(vec![RespValue::BulkString(Some("PING".to_string()))]));
+
+// Only parsing is skipped: the PING is still logged, passed through the hooks
+// and dispatched like any other command.
+fn try_inline_ping(
+    buf_reader: &mut BufReader<BudgetedStream>,
+    ctx: &ConnCtx,
+) -> std::io::Result<bool> {
+    let Some(len) = inline_ping_len(buf_reader.fill_buf()?) else {
+        return Ok(false);
+    };
+
+    buf_reader.consume(len);
+    if let Some(response) = execute(&INLINE_PING_COMMAND, ctx) {
+        marshal_to(&response, &mut buf_reader.get_mut().stream)?;
+    }
+    Ok(true)
+}
+
+fn refused_by_protected_mode(peer: std::net::IpAddr) -> bool {
+    PROTECTED_MODE.load(Ordering::Relaxed) && !peer.is_loopback()
+}
+
+fn handle_connection(mut stream: TcpStream) -> Result<(), std::io::Error> {
+    if refused_by_protected_mode(stream.peer_addr()?.ip()) {
+        let reply = RespValue::Error(PROTECTED_MODE_ERR.to_string());
+        return stream.write_all(&marshal(&reply));
+    }
+
+    let ctx = ConnCtx::new(stream.peer_addr()?.to_string());
+    let mut buf_reader = BufReader::new(BudgetedStream {
+        stream,
+        deadline: None,
+    });
+    let request_timeout = REQUEST_TIMEOUT_SECS.load(Ordering::Relaxed);
+    let max_request_bytes = MAX_REQUEST_BYTES.load(Ordering::Relaxed);
+    let mut decoder = Decoder::new().with_max_request_bytes(max_request_bytes as usize);
 
     loop {
-        let command = read_resp(&mut buf_reader)?;
+        // Idle connections wait without a deadline; the budget starts once a
+        // request begins to arrive.
+        if decoder.is_empty() {
+            buf_reader.get_mut().deadline = None;
+            if try_inline_ping(&mut buf_reader, &ctx)? {
+                continue;
+            }
+        }
+        if request_timeout > 0 && buf_reader.get_ref().deadline.is_none() {
+            buf_reader.get_mut().deadline =
+                Some(Instant::now() + Duration::from_secs(request_timeout));
+        }
+
+        let chunk = match buf_reader.fill_buf() {
+            Ok([]) => return Ok(()),
+            Ok(chunk) => chunk,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                ) =>
+            {
+                let reply = RespValue::Error("ERR request timed out".to_string());
+                buf_reader.get_mut().stream.write_all(&marshal(&reply))?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        let consumed = chunk.len();
+        let commands = decoder.feed(chunk);
+        buf_reader.consume(consumed);
+        if !commands.is_empty() {
+            buf_reader.get_mut().deadline = None;
+        }
+
+        // Replies to pipelined commands are written together.
+        let mut writer = BufWriter::new(&buf_reader.get_ref().stream);
+        for command in commands {
+            if let Some(response) = execute(&command, &ctx) {
+                marshal_to(&response, &mut writer)?;
+            }
+        }
+
+        if let Some(e) = decoder.take_error() {
+            let reply = RespValue::Error(format!("ERR {}", e));
+            marshal_to(&reply, &mut writer)?;
+            writer.flush()?;
+            return Err(e);
+        }
+        writer.flush()?;
+    }
+}
+
+fn execute(command: &RespValue, ctx: &ConnCtx) -> Option<RespValue> {
+    // Like Redis, an empty multibulk is silently ignored: no reply, no journal.
+    if matches!(command, RespValue::Array(arr) if arr.is_empty()) {
+        return None;
+    }
+
+    if let Err(e) = log_request(&ctx.peer, command) {
+        eprintln!("Request log write failed: {}", e);
+    }
+
+    let response = match run_hooks(command, ctx) {
+        Some(reply) => reply,
+        None => handle_resp(command, ctx),
+    };
+
+    let propagated = ctx.take_propagation();
+    if let Some(aof) = active_aof()
+        && is_write_command(command)
+        && !matches!(response, RespValue::Error(_))
+    {
+        let records = propagated
+            .as_deref()
+            .unwrap_or(std::slice::from_ref(command));
+        return Some(journal(aof, records, response));
+    }
+
+    Some(response)
+}
+
+// The write has already been applied, so a failed append leaves memory ahead
+// of the file. The client gets an error rather than a dropped connection, and
+// the divergence is counted in INFO persistence.
+fn journal(aof: &Mutex<Aof>, records: &[RespValue], response: RespValue) -> RespValue {
+    let mut aof = aof.lock().unwrap_or_else(PoisonError::into_inner);
+    match records.iter().try_for_each(|record| aof.write(record)) {
+        Ok(()) => response,
+        Err(e) => {
+            eprintln!(
+                "AOF write failed, change is applied but not journaled: {}",
+                e
+            );
+            UNJOURNALED_WRITES.fetch_add(1, Ordering::Relaxed);
+            RespValue::Error(format!("MISCONF Errors writing to the AOF file: {}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+    use std::time::Instant;
+
+    #[test]
+    fn protected_mode_refuses_only_external_peers() {
+        let _state = exclusive();
+        let external = "203.0.113.7".parse().unwrap();
+        let loopback = "127.0.0.1".parse().unwrap();
+
+        PROTECTED_MODE.store(true, Ordering::Relaxed);
+        let refused = (
+            refused_by_protected_mode(external),
+            refused_by_protected_mode(loopback),
+        );
+        PROTECTED_MODE.store(false, Ordering::Relaxed);
+
+        assert_eq!(refused, (true, false));
+        assert!(!refused_by_protected_mode(external));
+    }
+
+    #[test]
+    fn supervise_restarts_a_panicked_task() {
+        static RUNS: AtomicU64 = AtomicU64::new(0);
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let done_tx = Mutex::new(done_tx);
+
+        supervise("test-panicking-task", move || {
+            if RUNS.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("simulated background thread failure");
+            }
+            done_tx.lock().unwrap().send(()).unwrap();
+        });
 
-        let response = handle_resp(&command);
+        done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(RUNS.load(Ordering::SeqCst), 2);
+    }
+
+    // Serves a single connection on an ephemeral loopback port.
+    fn connect() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = handle_connection(stream);
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    fn read_reply(stream: &mut TcpStream, len: usize) -> String {
+        let mut buf = vec![0; len];
+        stream.read_exact(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn inline_ping_is_dispatched_like_any_command() {
+        let _state = exclusive();
+        run(&["CONFIG", "RESETSTAT"]);
+
+        let mut stream = connect();
+        stream.write_all(b"PING\r\n").unwrap();
+        assert_eq!(read_reply(&mut stream, 7), "+PONG\r\n");
+        stream.write_all(b"PING\r\nping\r\n").unwrap();
+        assert_eq!(read_reply(&mut stream, 14), "+PONG\r\n+PONG\r\n");
+
+        let RespValue::BulkString(Some(stats)) = run(&["INFO", "commandstats"]) else {
+            panic!("INFO replies with a bulk string");
+        };
+        assert!(stats.contains("cmdstat_ping:calls=3\r\n"));
+    }
+
+    #[test]
+    fn inline_ping_accepts_bare_lf_only_without_strict_crlf() {
+        let _state = exclusive();
+        let rejected = "-ERR Protocol error: expected CRLF line terminator\r\n";
+
+        let mut stream = connect();
+        stream.write_all(b"PING\n").unwrap();
+        assert_eq!(read_reply(&mut stream, rejected.len()), rejected);
+
+        STRICT_CRLF.store(false, Ordering::Relaxed);
+        let mut stream = connect();
+        // A leading inline PING takes the fast path; ones pipelined behind a
+        // framed command go through the decoder.
+        stream.write_all(b"PING\n").unwrap();
+        let fast = read_reply(&mut stream, 7);
+        stream
+            .write_all(b"*1\r\n$4\r\nPING\r\nPING\nping\n")
+            .unwrap();
+        let decoded = read_reply(&mut stream, 21);
+        STRICT_CRLF.store(true, Ordering::Relaxed);
+
+        assert_eq!(fast, "+PONG\r\n");
+        assert_eq!(decoded, "+PONG\r\n".repeat(3));
+    }
+
+    #[test]
+    #[ignore = "benchmark; run with `cargo test --release -- --ignored --nocapture`"]
+    fn bench_inline_ping_fast_path() {
+        const PINGS: u32 = 200_000;
+        const INLINE_PING: &[u8] = b"PING\r\n";
+        let _state = exclusive();
+        let ctx = ConnCtx::internal();
+
+        let start = Instant::now();
+        for _ in 0..PINGS {
+            assert_eq!(inline_ping_len(INLINE_PING), Some(INLINE_PING.len()));
+            execute(&INLINE_PING_COMMAND, &ctx);
+        }
+        let fast = start.elapsed();
+
+        let mut decoder = Decoder::new();
+        let start = Instant::now();
+        for _ in 0..PINGS {
+            for command in decoder.feed(INLINE_PING) {
+                execute(&command, &ctx);
+            }
+        }
+        let decoded = start.elapsed();
+
+        let rate = |elapsed: Duration| PINGS as f64 / elapsed.as_secs_f64();
+        println!(
+            "inline PING: fast path {:.0}/s, decoder {:.0}/s",
+            rate(fast),
+            rate(decoded)
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn failed_aof_write_is_replied_and_counted() {
+        let _state = exclusive();
+        // Writes to /dev/full fail with ENOSPC; the record is big enough to
+        // bypass the write buffer.
+        let aof = Mutex::new(Aof::new("/dev", "full", 1).unwrap());
+        let records = [command(&["SET", "unjournaled:k", &"v".repeat(16 * 1024)])];
+        let before = UNJOURNALED_WRITES.load(Ordering::Relaxed);
+
+        let reply = journal(&aof, &records, RespValue::SimpleString("OK".to_string()));
+
+        let RespValue::Error(e) = reply else {
+            panic!("expected an error reply, got {:?}", reply);
+        };
+        assert!(e.starts_with("MISCONF Errors writing to the AOF file: "));
+        assert_eq!(UNJOURNALED_WRITES.load(Ordering::Relaxed), before + 1);
+    }
+
+    #[test]
+    fn empty_multibulk_is_ignored_without_side_effects() {
+        let _state = exclusive();
+        let dirty = DIRTY.load(Ordering::Relaxed);
+        let ctx = ConnCtx::new("test".to_string());
+        assert!(execute(&RespValue::Array(vec![]), &ctx).is_none());
+        assert!(ctx.take_propagation().is_none());
+        assert_eq!(DIRTY.load(Ordering::Relaxed), dirty);
+
+        let mut stream = connect();
+        stream.write_all(b"*0\r\n*1\r\n$4\r\nPING\r\n").unwrap();
+        assert_eq!(read_reply(&mut stream, 7), "+PONG\r\n");
+    }
+
+    #[test]
+    fn listener_can_be_rebound_right_after_closing() {
+        let listener = bind_listener("127.0.0.1:0", true, false).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // The server side closes first, leaving its end in TIME_WAIT.
+        let mut client = TcpStream::connect(addr).unwrap();
+        drop(listener.accept().unwrap());
+        let _ = client.read(&mut [0; 1]);
+        drop(client);
+        drop(listener);
+
+        let rebound = bind_listener(&addr.to_string(), true, false).unwrap();
+        assert_eq!(rebound.local_addr().unwrap(), addr);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reuse_port_lets_two_listeners_share_a_port() {
+        let first = bind_listener("127.0.0.1:0", true, true).unwrap();
+        let addr = first.local_addr().unwrap().to_string();
+
+        let second = bind_listener(&addr, true, true).unwrap();
+        assert_eq!(second.local_addr().unwrap(), first.local_addr().unwrap());
+
+        let err = bind_listener(&addr, true, false).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn stalled_partial_multibulk_is_aborted() {
+        let _state = exclusive();
+        REQUEST_TIMEOUT_SECS.store(1, Ordering::Relaxed);
+        let mut stream = connect();
+
+        let start = Instant::now();
+        stream.write_all(b"*1000000\r\n$3\r\nSET\r\n").unwrap();
+        let mut reply = String::new();
+        // The server reads the timeout once the connection is handled, so
+        // keep it set until the reply is in.
+        let read = stream.read_to_string(&mut reply);
+        REQUEST_TIMEOUT_SECS.store(0, Ordering::Relaxed);
+        read.unwrap();
+
+        assert_eq!(reply, "-ERR request timed out\r\n");
+        assert!(start.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn oversized_header_line_is_refused() {
+        let _state = shared();
+        let mut stream = connect();
+        // Exactly the limit: the server has read everything before it closes,
+        // so the close does not reset the connection.
+        stream.write_all(&vec![b'x'; 64 * 1024]).unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "-ERR Protocol error: too big inline request\r\n");
+    }
+
+    #[test]
+    fn replay_only_summarises_a_known_aof() {
+        let _state = exclusive();
+        let dir = temp_dir("replay-only");
+        let path = dir.join("appendonly.aof");
+        let mut contents = Vec::new();
+        for args in [
+            &["SET", "replay:a", "1"][..],
+            &["SET", "replay:b", "2"],
+            &["DEL", "replay:a"],
+            &["NOSUCHCMD"],
+        ] {
+            contents.extend(marshal(&command(args)));
+        }
+        let torn_at = contents.len();
+        contents.extend(b"*2\r\n$3\r\nGET");
+        std::fs::write(&path, &contents).unwrap();
+
+        let saved = std::mem::take(&mut *write_cache());
+        let report = replay_only(path.to_str().unwrap(), false);
+        *write_cache() = saved;
+
+        assert_eq!(
+            report.unwrap(),
+            format!(
+                "Replay summary for {}\n  del: 1\n  nosuchcmd: 1\n  set: 2\n  total: 4\n  \
+                 errors: 1\n  keys: 1\n  invalid tail at offset {}\n",
+                path.display(),
+                torn_at
+            )
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), contents);
+    }
+
+    // Parses the command line with --config pointing at the given contents.
+    fn parse_with_config(
+        name: &str,
+        contents: &str,
+        cli: &[&str],
+    ) -> std::io::Result<(Args, Vec<(String, String)>)> {
+        let path = temp_dir(name).join("redis.conf");
+        std::fs::write(&path, contents).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let argv = ["redis-lite", "--config", &path]
+            .into_iter()
+            .chain(cli.iter().copied());
+        let matches = Args::command().get_matches_from(argv);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        let params = read_config_file(&path, &mut args, &matches)?;
+        Ok((args, params))
+    }
+
+    #[test]
+    fn config_file_settings_take_effect_below_cli_flags() {
+        let _state = shared();
+        let (args, params) = parse_with_config(
+            "config-file",
+            "# test config\nport 7001\nbind 127.0.0.1\nappendonly no\ndir /from/file\n\
+             max-string-bytes 16\nappendfsync everysec\n",
+            &["--dir", "/from/cli"],
+        )
+        .unwrap();
+
+        assert_eq!(args.addr, "127.0.0.1:7001");
+        assert!(args.no_persistence);
+        assert_eq!(args.dir, "/from/cli");
+        assert_eq!(
+            params,
+            vec![("max-string-bytes".to_string(), "16".to_string())]
+        );
+    }
+
+    #[test]
+    fn config_file_with_requirepass_is_refused() {
+        let _state = shared();
+        let err = parse_with_config("config-requirepass", "port 7001\nrequirepass secret\n", &[])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "line 2: requirepass is not supported: this server has no AUTH"
+        );
 
-        aof.lock().unwrap().write(&command)?;
-        buf_reader
-            .get_mut()
-            .write_all(marshal(&response).as_ref())
+        let err = parse_with_config("config-unknown", "no-such-directive 1\n", &[])
+            .err()
             .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "line 1: unknown directive 'no-such-directive'"
+        );
     }
 }
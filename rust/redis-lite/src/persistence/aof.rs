@@ -1,57 +1,462 @@
 use crate::prelude::*;
 
+static ACTIVE: OnceCell<Arc<Mutex<Aof>>> = OnceCell::new();
+
+// Set through DEBUG AOF-SYNC-FAIL to exercise the sync error path.
+pub static SYNC_FAULT: AtomicBool = AtomicBool::new(false);
+
+// Writes applied in memory whose AOF append failed.
+pub static UNJOURNALED_WRITES: AtomicU64 = AtomicU64::new(0);
+
+// Benchmark sink: records are encoded as usual, but the buffer is cleared
+// whenever it would outgrow its bound, so nothing touches the disk.
+const MEMORY_SINK_LIMIT: usize = 64 * 1024 * 1024;
+
+enum Sink {
+    File(BufWriter<File>),
+    Memory(Vec<u8>),
+}
+
+impl Write for Sink {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Sink::File(writer) => writer.write(data),
+            Sink::Memory(buf) => {
+                if buf.len() + data.len() > MEMORY_SINK_LIMIT {
+                    buf.clear();
+                }
+                buf.extend_from_slice(data);
+                Ok(data.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Sink::File(writer) => writer.flush(),
+            Sink::Memory(_) => Ok(()),
+        }
+    }
+}
+
 pub struct Aof {
-    reader: BufReader<File>,
-    writer: BufWriter<File>,
+    reader: Option<BufReader<File>>,
+    writer: Sink,
     lock: Mutex<()>,
     sync_period: Duration,
+    checksums: bool,
+    repair: bool,
+}
+
+#[derive(Default)]
+pub struct ReplaySummary {
+    pub commands: HashMap<String, u64>,
+    pub errors: u64,
+    pub torn_tail_at: Option<u64>,
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn read_checksum(reader: &mut BufReader<File>) -> std::io::Result<Option<u32>> {
+    if reader.fill_buf()?.first() != Some(&b'#') {
+        return Ok(None);
+    }
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    // A crash while the line was being written leaves it cut short; that is a
+    // torn tail to truncate, not a corrupt file.
+    let digits = line[1..].trim();
+    if !line.ends_with('\n') || digits.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "Truncated AOF checksum",
+        ));
+    }
+    u32::from_str_radix(digits, 16)
+        .map(Some)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid AOF checksum"))
+}
+
+// Drops a torn or corrupt tail so that new records are not appended after it.
+fn truncate(
+    writer: &mut Sink,
+    offset: u64,
+    repair: bool,
+    summary: &mut ReplaySummary,
+) -> std::io::Result<()> {
+    let Sink::File(writer) = writer else {
+        return Ok(());
+    };
+    if writer.get_ref().metadata()?.len() > offset {
+        summary.torn_tail_at = Some(offset);
+        if !repair {
+            eprintln!("AOF has an invalid tail at offset {}", offset);
+            return Ok(());
+        }
+        eprintln!("Truncating AOF to last valid record at offset {}", offset);
+        writer.flush()?;
+        writer.get_ref().set_len(offset)?;
+    }
+    Ok(())
 }
 
 impl Aof {
-    pub fn new(path: &str, sync_period_secs: u64) -> std::io::Result<Self> {
+    pub fn new(dir: &str, filename: &str, sync_period_secs: u64) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = Path::new(dir).join(filename);
+
         let write_file = std::fs::OpenOptions::new()
             .create(true)
             .append(true)
-            .open(path)?;
+            .open(&path)?;
 
-        let read_file = std::fs::OpenOptions::new().read(true).open(path)?;
+        let read_file = std::fs::OpenOptions::new().read(true).open(&path)?;
 
         let reader = BufReader::new(read_file);
         let writer = BufWriter::new(write_file);
 
         Ok(Self {
-            reader,
-            writer,
+            reader: Some(reader),
+            writer: Sink::File(writer),
             lock: Mutex::new(()),
             sync_period: Duration::from_secs(sync_period_secs),
+            checksums: false,
+            repair: true,
         })
     }
 
-    pub fn read(&mut self) -> std::io::Result<()> {
-        let _lock = self.lock.lock().unwrap();
+    pub fn in_memory(sync_period_secs: u64) -> Self {
+        Self {
+            reader: None,
+            writer: Sink::Memory(Vec::new()),
+            lock: Mutex::new(()),
+            sync_period: Duration::from_secs(sync_period_secs),
+            checksums: false,
+            repair: true,
+        }
+    }
+
+    pub fn with_checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    // With repair off, a torn tail is reported but the file is left untouched.
+    pub fn with_repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+
+    pub fn sync_period(&self) -> Duration {
+        self.sync_period
+    }
+
+    pub fn read(&mut self) -> std::io::Result<ReplaySummary> {
+        let Self {
+            reader,
+            writer,
+            repair,
+            ..
+        } = self;
+        let ctx = ConnCtx::internal();
+        let mut summary = ReplaySummary::default();
+        let Some(reader) = reader else {
+            return Ok(summary);
+        };
         loop {
-            match read_resp(&mut self.reader) {
+            let offset = reader.stream_position()?;
+            let checksum = match read_checksum(reader) {
+                Ok(checksum) => checksum,
+                Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    truncate(writer, offset, *repair, &mut summary)?;
+                    return Ok(summary);
+                }
+                Err(e) => return Err(e),
+            };
+
+            match read_resp(reader) {
                 Ok(command) => {
+                    if let Some(expected) = checksum
+                        && crc32(&marshal(&command)) != expected
+                    {
+                        eprintln!("AOF checksum mismatch at offset {}", offset);
+                        truncate(writer, offset, *repair, &mut summary)?;
+                        return Ok(summary);
+                    }
+
                     println!("Replaying command: {:?}", command);
-                    let _ = handle_resp(&command);
+                    if let RespValue::Array(arr) = &command
+                        && let Some(RespValue::BulkString(Some(name))) = arr.first()
+                    {
+                        *summary.commands.entry(name.to_lowercase()).or_default() += 1;
+                    }
+                    // Commands this build does not understand are skipped so that
+                    // files written by newer versions still load.
+                    if let RespValue::Error(e) = handle_resp(&command, &ctx) {
+                        eprintln!("Skipping AOF command ({}):\n{}", e, format_resp(&command));
+                        summary.errors += 1;
+                    }
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    truncate(writer, offset, *repair, &mut summary)?;
+                    return Ok(summary);
                 }
-                Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
                 Err(e) => return Err(e),
             }
         }
-        Ok(())
     }
 
     pub fn write(&mut self, val: &RespValue) -> std::io::Result<()> {
-        let _lock = self.lock.lock().unwrap();
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
         let bytes = marshal(val);
+        if self.checksums {
+            write!(self.writer, "#{:08x}\r\n", crc32(&bytes))?;
+        }
         self.writer.write_all(&bytes)?;
         Ok(())
     }
 
-    pub fn sync(&mut self) -> std::io::Result<()> {
-        let _lock = self.lock.lock().unwrap();
+    // Hands back a second handle to the file so the caller can fsync it
+    // without holding the lock that writers need.
+    fn flush(&mut self) -> std::io::Result<Option<File>> {
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if SYNC_FAULT.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other("injected sync failure"));
+        }
         self.writer.flush()?;
-        Ok(())
+        match &self.writer {
+            Sink::File(writer) => Ok(Some(writer.get_ref().try_clone()?)),
+            Sink::Memory(_) => Ok(None),
+        }
+    }
+}
+
+// Flushes under the lock, then fsyncs outside it so writers only wait for the
+// flush and not for the disk.
+pub fn sync_aof(aof: &Mutex<Aof>) -> std::io::Result<()> {
+    let file = aof.lock().unwrap_or_else(PoisonError::into_inner).flush()?;
+    if let Some(file) = file {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+pub fn set_active_aof(aof: Arc<Mutex<Aof>>) {
+    let _ = ACTIVE.set(aof);
+}
+
+pub fn active_aof() -> Option<&'static Arc<Mutex<Aof>>> {
+    ACTIVE.get()
+}
+
+pub fn aof_enabled() -> bool {
+    ACTIVE.get().is_some()
+}
+
+pub fn sync_active_aof() -> std::io::Result<()> {
+    match ACTIVE.get() {
+        Some(aof) => sync_aof(aof),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn sync_puts_written_records_on_disk() {
+        let _state = shared();
+        let dir = temp_dir("aof-sync");
+        let aof = Aof::new(dir.to_str().unwrap(), "aof.log", 1).unwrap();
+        let aof = Mutex::new(aof);
+
+        let set = command(&["SET", "k", "v"]);
+        aof.lock().unwrap().write(&set).unwrap();
+        assert!(std::fs::read(dir.join("aof.log")).unwrap().is_empty());
+
+        sync_aof(&aof).unwrap();
+        assert_eq!(std::fs::read(dir.join("aof.log")).unwrap(), marshal(&set));
+    }
+
+    #[test]
+    fn poisoned_aof_lock_does_not_stop_writes_or_syncs() {
+        let _state = shared();
+        let dir = temp_dir("aof-poisoned");
+        let aof = Arc::new(Mutex::new(
+            Aof::new(dir.to_str().unwrap(), "aof.log", 1).unwrap(),
+        ));
+
+        let holder = Arc::clone(&aof);
+        let _ = thread::spawn(move || {
+            let _guard = holder.lock().unwrap();
+            panic!("simulated sync thread failure");
+        })
+        .join();
+        assert!(aof.is_poisoned());
+
+        let set = command(&["SET", "k", "v"]);
+        aof.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(&set)
+            .unwrap();
+        sync_aof(&aof).unwrap();
+        assert_eq!(std::fs::read(dir.join("aof.log")).unwrap(), marshal(&set));
+    }
+
+    #[test]
+    fn replays_a_legacy_aof_of_string_sets() {
+        let _state = shared();
+        let dir = temp_dir("aof-legacy");
+        let legacy = "*3\r\n$3\r\nSET\r\n$12\r\nlegacy:first\r\n$5\r\nhello\r\n\
+                      *3\r\n$3\r\nset\r\n$13\r\nlegacy:second\r\n$3\r\n123\r\n\
+                      *3\r\n$3\r\nSET\r\n$13\r\nlegacy:doomed\r\n$1\r\nx\r\n\
+                      *2\r\n$3\r\nDEL\r\n$13\r\nlegacy:doomed\r\n\
+                      *2\r\n$9\r\nFUTURECMD\r\n$3\r\narg\r\n";
+        std::fs::write(dir.join("aof.log"), legacy).unwrap();
+
+        let summary = Aof::new(dir.to_str().unwrap(), "aof.log", 1)
+            .unwrap()
+            .read()
+            .unwrap();
+
+        assert_eq!(summary.commands["set"], 3);
+        assert_eq!(summary.commands["del"], 1);
+        assert_eq!(summary.errors, 1);
+        assert_eq!(summary.torn_tail_at, None);
+        let map = read_cache();
+        assert_eq!(map.get("legacy:first"), Some(&bulk("hello")));
+        assert_eq!(map.get("legacy:second"), Some(&bulk("123")));
+        assert_eq!(map.get("legacy:doomed"), None);
+    }
+
+    #[test]
+    fn corrupted_record_fails_its_checksum_and_is_truncated() {
+        let _state = shared();
+        let dir = temp_dir("aof-checksum");
+        let path = dir.to_str().unwrap();
+        let mut aof = Aof::new(path, "aof.log", 1).unwrap().with_checksums(true);
+        aof.write(&command(&["SET", "crc:good", "intact"])).unwrap();
+        aof.write(&command(&["SET", "crc:bad", "flipped"])).unwrap();
+        drop(aof);
+
+        let file = dir.join("aof.log");
+        let mut bytes = std::fs::read(&file).unwrap();
+        let good_len = bytes.iter().position(|&b| b == b'*').unwrap()
+            + marshal(&command(&["SET", "crc:good", "intact"])).len();
+        let tail = bytes.len() - 3;
+        bytes[tail] = b'X';
+        std::fs::write(&file, &bytes).unwrap();
+
+        let summary = Aof::new(path, "aof.log", 1)
+            .unwrap()
+            .with_checksums(true)
+            .read()
+            .unwrap();
+
+        assert_eq!(summary.torn_tail_at, Some(good_len as u64));
+        assert_eq!(std::fs::metadata(&file).unwrap().len(), good_len as u64);
+        assert_eq!(read_cache().get("crc:good"), Some(&bulk("intact")));
+        assert_eq!(read_cache().get("crc:bad"), None);
+    }
+
+    #[test]
+    fn aof_is_created_under_the_configured_dir_and_name() {
+        let root = temp_dir("aof-dir");
+        let dir = root.join("nested").join("data");
+        let aof = Aof::new(dir.to_str().unwrap(), "custom.aof", 1).unwrap();
+        drop(aof);
+
+        assert!(dir.join("custom.aof").is_file());
+        assert!(!root.join("custom.aof").exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn truncated_mset_record_applies_none_of_its_pairs() {
+        let _state = shared();
+        let dir = temp_dir("aof-mset");
+        let path = dir.to_str().unwrap();
+        let set = command(&["SET", "mset:before", "kept"]);
+        let mset = command(&["MSET", "mset:a", "1", "mset:b", "2", "mset:c", "3"]);
+
+        // MSET is journaled as the single record the client sent.
+        let ctx = ConnCtx::internal();
+        handle_resp(&mset, &ctx);
+        assert_eq!(ctx.take_propagation(), None);
+        for key in ["mset:a", "mset:b", "mset:c"] {
+            write_cache().remove(key);
+        }
+
+        let mut aof = Aof::new(path, "aof.log", 1).unwrap();
+        aof.write(&set).unwrap();
+        aof.write(&mset).unwrap();
+        drop(aof);
+
+        let file = dir.join("aof.log");
+        let good_len = marshal(&set).len();
+        let torn = good_len + marshal(&mset).len() - 12;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_len(torn as u64)
+            .unwrap();
+
+        let summary = Aof::new(path, "aof.log", 1).unwrap().read().unwrap();
+
+        assert_eq!(summary.torn_tail_at, Some(good_len as u64));
+        assert_eq!(summary.commands.get("mset"), None);
+        assert_eq!(std::fs::metadata(&file).unwrap().len(), good_len as u64);
+        assert_eq!(read_cache().get("mset:before"), Some(&bulk("kept")));
+        for key in ["mset:a", "mset:b", "mset:c"] {
+            assert_eq!(read_cache().get(key), None, "{} was applied", key);
+        }
+    }
+
+    #[test]
+    fn checksum_line_cut_short_is_truncated_as_a_torn_tail() {
+        let _state = shared();
+        let dir = temp_dir("aof-torn-checksum");
+        let path = dir.to_str().unwrap();
+        let mut aof = Aof::new(path, "aof.log", 1).unwrap().with_checksums(true);
+        aof.write(&command(&["SET", "torncrc:k", "v"])).unwrap();
+        drop(aof);
+
+        let file = dir.join("aof.log");
+        let good_len = std::fs::metadata(&file).unwrap().len();
+        for tail in [&b"#"[..], b"#1a2b", b"#\n"] {
+            let mut bytes = std::fs::read(&file).unwrap();
+            bytes.truncate(good_len as usize);
+            bytes.extend_from_slice(tail);
+            std::fs::write(&file, &bytes).unwrap();
+
+            let summary = Aof::new(path, "aof.log", 1)
+                .unwrap()
+                .with_checksums(true)
+                .read()
+                .unwrap();
+
+            assert_eq!(summary.torn_tail_at, Some(good_len), "{:?}", tail);
+            assert_eq!(
+                std::fs::metadata(&file).unwrap().len(),
+                good_len,
+                "{:?}",
+                tail
+            );
+            assert_eq!(read_cache().get("torncrc:k"), Some(&bulk("v")));
+        }
     }
 }
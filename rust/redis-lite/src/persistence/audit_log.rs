@@ -0,0 +1,165 @@
+use crate::prelude::*;
+
+static AUDIT_LOG: OnceCell<Mutex<AuditLog>> = OnceCell::new();
+
+pub struct AuditLog {
+    writer: BufWriter<File>,
+    patterns: Vec<String>,
+}
+
+impl AuditLog {
+    pub fn new(path: &str, patterns: Vec<String>) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            patterns,
+        })
+    }
+
+    pub fn record(&mut self, name: &str, keys: &[String], ctx: &ConnCtx) -> std::io::Result<()> {
+        for key in keys {
+            if self.patterns.iter().any(|p| glob_match(p, key)) {
+                writeln!(
+                    self.writer,
+                    "{} [{}] {} {:?}",
+                    log_timestamp(),
+                    ctx.peer,
+                    name,
+                    key
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub fn open_audit_log(path: &str, patterns: Vec<String>) -> std::io::Result<()> {
+    let log = AuditLog::new(path, patterns)?;
+    let _ = AUDIT_LOG.set(Mutex::new(log));
+    Ok(())
+}
+
+// The command name and the keys it touches. KEYS and DELMATCH name no keys of
+// their own, so the keys their pattern currently reaches are used instead,
+// capped the way KEYS ... COUNT caps its reply.
+fn audited_keys(command: &RespValue) -> Option<(String, Vec<String>)> {
+    let RespValue::Array(arr) = command else {
+        return None;
+    };
+    let Some(RespValue::BulkString(Some(name))) = arr.first() else {
+        return None;
+    };
+    let name = name.to_lowercase();
+
+    let keys = match (name.as_str(), arr.get(1)) {
+        ("keys", Some(RespValue::BulkString(Some(pattern)))) => match keys_limit(&arr[2..]) {
+            Ok(limit) => keys_matching(pattern, limit),
+            Err(_) => Vec::new(),
+        },
+        ("delmatch", Some(RespValue::BulkString(Some(pattern)))) => {
+            keys_matching(pattern, usize::MAX)
+        }
+        _ => command_keys(command)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+    };
+    Some((name, keys))
+}
+
+pub fn audit_hook(command: &RespValue, ctx: &ConnCtx) -> Option<RespValue> {
+    let log = AUDIT_LOG.get()?;
+    // Collected before taking the lock, since a pattern command scans the store.
+    let (name, keys) = audited_keys(command)?;
+    if let Err(e) = log
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .record(&name, &keys, ctx)
+    {
+        eprintln!("Audit log write failed: {}", e);
+    }
+    None
+}
+
+pub fn flush_audit_log() -> std::io::Result<()> {
+    match AUDIT_LOG.get() {
+        Some(log) => log.lock().unwrap_or_else(PoisonError::into_inner).flush(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    fn audited_lines(path: &std::path::Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1.to_string())
+            .collect()
+    }
+
+    fn record(log: &mut AuditLog, args: &[&str]) {
+        let (name, keys) = audited_keys(&command(args)).unwrap();
+        let ctx = ConnCtx::new("10.0.0.1:4000".to_string());
+        log.record(&name, &keys, &ctx).unwrap();
+    }
+
+    #[test]
+    fn only_keys_matching_an_audit_pattern_are_recorded() {
+        let _state = shared();
+        let path = temp_dir("audit-log").join("audit.log");
+        let mut log = AuditLog::new(path.to_str().unwrap(), vec!["audit:*".to_string()]).unwrap();
+
+        record(&mut log, &["GET", "audit:card"]);
+        record(&mut log, &["GET", "public:page"]);
+        record(&mut log, &["DEL", "public:a", "audit:pin"]);
+        log.flush().unwrap();
+
+        assert_eq!(
+            audited_lines(&path),
+            vec![
+                "[10.0.0.1:4000] get \"audit:card\"",
+                "[10.0.0.1:4000] del \"audit:pin\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn pattern_commands_record_the_audited_keys_they_reach() {
+        let _state = shared();
+        for key in ["auditpat:secret:a", "auditpat:secret:b", "auditpat:plain"] {
+            write_cache().insert(key.to_string(), bulk("v"));
+        }
+        let path = temp_dir("audit-log-pattern").join("audit.log");
+        let patterns = vec!["auditpat:secret:*".to_string()];
+        let mut log = AuditLog::new(path.to_str().unwrap(), patterns).unwrap();
+
+        record(&mut log, &["KEYS", "auditpat:*"]);
+        record(&mut log, &["KEYS", "auditpat:secret:*", "COUNT", "1"]);
+        record(&mut log, &["KEYS", "auditpat:*", "COUNT", "x"]);
+        record(&mut log, &["DELMATCH", "auditpat:secret:*"]);
+        log.flush().unwrap();
+
+        assert_eq!(
+            audited_lines(&path),
+            vec![
+                "[10.0.0.1:4000] keys \"auditpat:secret:a\"",
+                "[10.0.0.1:4000] keys \"auditpat:secret:b\"",
+                "[10.0.0.1:4000] keys \"auditpat:secret:a\"",
+                "[10.0.0.1:4000] delmatch \"auditpat:secret:a\"",
+                "[10.0.0.1:4000] delmatch \"auditpat:secret:b\"",
+            ]
+        );
+    }
+}
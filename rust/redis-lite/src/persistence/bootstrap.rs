@@ -0,0 +1,127 @@
+use crate::prelude::*;
+
+pub enum StartupSource {
+    Aof,
+    Empty,
+}
+
+pub fn load_persistence(
+    dir: &str,
+    appendfilename: &str,
+    aof_enabled: bool,
+    checksums: bool,
+    in_memory: bool,
+) -> std::io::Result<(Option<Aof>, StartupSource)> {
+    if !aof_enabled {
+        return Ok((None, StartupSource::Empty));
+    }
+    if in_memory {
+        let aof = Aof::in_memory(1).with_checksums(checksums);
+        return Ok((Some(aof), StartupSource::Empty));
+    }
+
+    let existed = Path::new(dir).join(appendfilename).exists();
+    let mut aof = Aof::new(dir, appendfilename, 1)?.with_checksums(checksums);
+
+    if !existed {
+        return Ok((Some(aof), StartupSource::Empty));
+    }
+
+    let summary = aof.read()?;
+    if summary.errors > 0 {
+        eprintln!("Skipped {} AOF commands during load", summary.errors);
+    }
+    Ok((Some(aof), StartupSource::Aof))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn disabled_persistence_creates_no_aof() {
+        let _state = shared();
+        let dir = temp_dir("no-persistence");
+        let (aof, source) =
+            load_persistence(dir.to_str().unwrap(), "aof.log", false, false, false).unwrap();
+
+        assert!(aof.is_none());
+        assert!(matches!(source, StartupSource::Empty));
+        assert!(!dir.join("aof.log").exists());
+
+        let ctx = ConnCtx::new("test".to_string());
+        handle_resp(&command(&["SET", "nopersist:k", "v"]), &ctx);
+        assert_eq!(
+            handle_resp(&command(&["GET", "nopersist:k"]), &ctx),
+            bulk("v")
+        );
+    }
+
+    #[test]
+    fn existing_aof_is_loaded() {
+        let _state = shared();
+        let dir = temp_dir("bootstrap-aof");
+        std::fs::write(
+            dir.join("aof.log"),
+            marshal(&command(&["SET", "bootstrap:k", "from-aof"])),
+        )
+        .unwrap();
+
+        let (aof, source) =
+            load_persistence(dir.to_str().unwrap(), "aof.log", true, false, false).unwrap();
+        assert!(aof.is_some());
+        assert!(matches!(source, StartupSource::Aof));
+        assert_eq!(read_cache().get("bootstrap:k"), Some(&bulk("from-aof")));
+    }
+
+    // There is no snapshot loader, so a directory holding only a snapshot
+    // starts empty, the same as one holding nothing.
+    #[test]
+    fn missing_aof_starts_empty_and_creates_it() {
+        let _state = shared();
+        for (name, files) in [
+            ("bootstrap-empty", &[][..]),
+            ("bootstrap-rdb", &["dump.rdb"][..]),
+        ] {
+            let dir = temp_dir(name);
+            for file in files {
+                std::fs::write(dir.join(file), "REDIS0011").unwrap();
+            }
+
+            let (aof, source) =
+                load_persistence(dir.to_str().unwrap(), "aof.log", true, false, false).unwrap();
+            assert!(aof.is_some());
+            assert!(matches!(source, StartupSource::Empty), "{}", name);
+            assert!(dir.join("aof.log").exists());
+        }
+    }
+
+    #[test]
+    fn in_memory_aof_journals_writes_without_a_file() {
+        let _state = shared();
+        let dir = temp_dir("aof-in-memory");
+        let (aof, source) =
+            load_persistence(dir.to_str().unwrap(), "aof.log", true, true, true).unwrap();
+        assert!(matches!(source, StartupSource::Empty));
+        let aof = Mutex::new(aof.unwrap());
+
+        let ctx = ConnCtx::new("test".to_string());
+        for i in 0..1000 {
+            let set = command(&["SET", &format!("inmemory:{}", i), "v"]);
+            assert_eq!(
+                handle_resp(&set, &ctx),
+                RespValue::SimpleString("OK".into())
+            );
+            aof.lock().unwrap().write(&set).unwrap();
+        }
+        sync_aof(&aof).unwrap();
+
+        assert_eq!(
+            handle_resp(&command(&["GET", "inmemory:999"]), &ctx),
+            bulk("v")
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        run(&["DELMATCH", "inmemory:*"]);
+    }
+}
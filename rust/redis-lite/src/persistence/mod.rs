@@ -1,4 +1,9 @@
-use crate::prelude::*;
-
 pub mod aof;
-pub use aof::Aof;
+pub mod audit_log;
+pub mod bootstrap;
+pub mod request_log;
+
+pub use aof::*;
+pub use audit_log::*;
+pub use bootstrap::*;
+pub use request_log::*;
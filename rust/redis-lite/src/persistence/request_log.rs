@@ -0,0 +1,91 @@
+use crate::prelude::*;
+
+static REQUEST_LOG: OnceCell<Mutex<RequestLog>> = OnceCell::new();
+
+pub fn log_timestamp() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}.{:06}", now.as_secs(), now.subsec_micros())
+}
+
+pub struct RequestLog {
+    writer: BufWriter<File>,
+}
+
+impl RequestLog {
+    pub fn new(path: &str) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, addr: &str, command: &RespValue) -> std::io::Result<()> {
+        write!(self.writer, "{} [{}]", log_timestamp(), addr)?;
+        if let RespValue::Array(arr) = command {
+            for arg in arr {
+                match arg {
+                    RespValue::BulkString(Some(s)) => write!(self.writer, " {:?}", s)?,
+                    other => write!(self.writer, " {:?}", other)?,
+                }
+            }
+        }
+        writeln!(self.writer)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub fn open_request_log(path: &str) -> std::io::Result<()> {
+    let log = RequestLog::new(path)?;
+    let _ = REQUEST_LOG.set(Mutex::new(log));
+    Ok(())
+}
+
+pub fn log_request(addr: &str, command: &RespValue) -> std::io::Result<()> {
+    match REQUEST_LOG.get() {
+        Some(log) => log
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(addr, command),
+        None => Ok(()),
+    }
+}
+
+pub fn flush_request_log() -> std::io::Result<()> {
+    match REQUEST_LOG.get() {
+        Some(log) => log.lock().unwrap_or_else(PoisonError::into_inner).flush(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn recorded_commands_appear_in_the_log() {
+        let path = temp_dir("request-log").join("requests.log");
+        let mut log = RequestLog::new(path.to_str().unwrap()).unwrap();
+
+        log.record("127.0.0.1:5000", &command(&["SET", "k", "v"]))
+            .unwrap();
+        log.record("127.0.0.1:5001", &command(&["GET", "k"]))
+            .unwrap();
+        log.flush().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" [127.0.0.1:5000] \"SET\" \"k\" \"v\""));
+        assert!(lines[1].ends_with(" [127.0.0.1:5001] \"GET\" \"k\""));
+    }
+}
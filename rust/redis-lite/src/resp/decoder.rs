@@ -0,0 +1,401 @@
+use crate::prelude::*;
+
+// Same limits as Redis' PROTO_INLINE_MAX_SIZE and client-query-buffer-limit.
+// Together with the time budget in handle_connection they stop a client from
+// tying up memory with a request that never completes.
+const MAX_LINE_LEN: usize = 64 * 1024;
+// Same threshold as Redis' PROTO_MBULK_BIG_ARG: payloads at least this big
+// are handed over without a copy.
+const BIG_ARG: usize = 32 * 1024;
+pub const DEFAULT_MAX_REQUEST_BYTES: u64 = 1024 * 1024 * 1024;
+
+// Incremental counterpart to read_resp: bytes are fed in as they arrive and
+// complete requests come out, so parsing does not depend on a blocking reader.
+pub struct Decoder {
+    buf: Vec<u8>,
+    pos: usize,
+    frame: Option<Frame>,
+    error: Option<std::io::Error>,
+    max_request_bytes: usize,
+}
+
+struct Frame {
+    expected: usize,
+    elements: Vec<RespValue>,
+    bulk_len: Option<usize>,
+    // Bytes of this request consumed so far, headers included.
+    size: usize,
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn take_line(buf: &[u8], pos: &mut usize) -> std::io::Result<Option<String>> {
+    let rest = &buf[*pos..];
+    let end = rest.iter().take(MAX_LINE_LEN).position(|&b| b == b'\n');
+    let Some(end) = end else {
+        if rest.len() >= MAX_LINE_LEN {
+            return Err(invalid_data("Protocol error: too big inline request"));
+        }
+        return Ok(None);
+    };
+    let line = String::from_utf8_lossy(&rest[..=end]).into_owned();
+    check_line_terminator(&line)?;
+    *pos += end + 1;
+    Ok(Some(line))
+}
+
+// Length of the inline PING that buf starts with, if any. Without
+// --strict-crlf a bare LF ends it, as it would any other line.
+pub(crate) fn inline_ping_len(buf: &[u8]) -> Option<usize> {
+    if !buf.get(..4)?.eq_ignore_ascii_case(b"PING") {
+        return None;
+    }
+    match &buf[4..] {
+        [b'\r', b'\n', ..] => Some(6),
+        [b'\n', ..] if !STRICT_CRLF.load(Ordering::Relaxed) => Some(5),
+        _ => None,
+    }
+}
+
+fn request_too_big() -> std::io::Error {
+    invalid_data("Protocol error: request exceeds the size limit")
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            pos: 0,
+            frame: None,
+            error: None,
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES as usize,
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_request_bytes(mut self, max: usize) -> Self {
+        self.max_request_bytes = max;
+        self
+    }
+
+    // True when no partial request is buffered.
+    pub fn is_empty(&self) -> bool {
+        self.frame.is_none() && self.pos == self.buf.len()
+    }
+
+    // Returns the requests completed by this input. Requests before a protocol
+    // error are still returned; the error is then reported by take_error and
+    // the decoder accepts no further input.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<RespValue> {
+        let mut frames = Vec::new();
+        if self.error.is_some() {
+            return frames;
+        }
+
+        self.reserve_for_bulk(data.len());
+        self.buf.extend_from_slice(data);
+        loop {
+            match self.next_frame() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break,
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            }
+        }
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        frames
+    }
+
+    // Grows the buffer towards a pending bulk payload only as its bytes
+    // arrive, at most doubling each time, so a client cannot make the server
+    // allocate a large declared length it never sends. Reserving exactly also
+    // keeps the payload handed to the store from carrying spare capacity.
+    fn reserve_for_bulk(&mut self, incoming: usize) {
+        let Some(len) = self.frame.as_ref().and_then(|f| f.bulk_len) else {
+            return;
+        };
+        let have = self.buf.len();
+        if have + incoming <= self.buf.capacity() {
+            return;
+        }
+        let target = (len + 2).min((2 * have).max(have + incoming));
+        self.buf.reserve_exact(target.saturating_sub(have));
+    }
+
+    pub fn take_error(&mut self) -> Option<std::io::Error> {
+        self.error.take()
+    }
+
+    fn next_frame(&mut self) -> std::io::Result<Option<RespValue>> {
+        let Self {
+            buf,
+            pos,
+            frame,
+            max_request_bytes,
+            ..
+        } = self;
+        loop {
+            let Some(current) = frame else {
+                let Some(line) = take_line(buf, pos)? else {
+                    return Ok(None);
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // Pipelined inline PINGs; a leading one takes the fast path in
+                // handle_connection before reaching the decoder.
+                if inline_ping_len(line.as_bytes()) == Some(line.len()) {
+                    return Ok(Some(RespValue::Array(vec![RespValue::BulkString(Some(
+                        "PING".to_string(),
+                    ))])));
+                }
+                if let Some(command) = parse_lenient_line(&line)? {
+                    return Ok(Some(command));
+                }
+                let expected = parse_array_header(&line)?;
+                *frame = Some(Frame {
+                    expected,
+                    elements: Vec::with_capacity(expected.min(1024)),
+                    bulk_len: None,
+                    size: line.len(),
+                });
+                continue;
+            };
+
+            if current.elements.len() == current.expected {
+                let done = frame.take().unwrap();
+                return Ok(Some(RespValue::Array(done.elements)));
+            }
+
+            match current.bulk_len {
+                Some(len) => {
+                    if buf.len() - *pos < len {
+                        return Ok(None);
+                    }
+                    let mut end = *pos + len;
+                    let Some(terminator) = take_line(buf, &mut end)? else {
+                        return Ok(None);
+                    };
+                    check_bulk_terminator(&terminator)?;
+                    current.size += len + terminator.len();
+
+                    let payload = if len < BIG_ARG {
+                        let payload = buf[*pos..*pos + len].to_vec();
+                        *pos = end;
+                        payload
+                    } else {
+                        // Hand a large payload over by splitting the buffer
+                        // instead of copying it, so it is not held twice. This
+                        // moves whatever follows it, which is why small
+                        // payloads are copied instead.
+                        buf.drain(..*pos);
+                        let rest = buf.split_off(len);
+                        let payload = std::mem::replace(buf, rest);
+                        *pos = end - *pos - len;
+                        payload
+                    };
+                    current
+                        .elements
+                        .push(RespValue::BulkString(Some(bulk_to_string(payload))));
+                    current.bulk_len = None;
+                }
+                None => {
+                    let Some(line) = take_line(buf, pos)? else {
+                        return Ok(None);
+                    };
+                    current.size += line.len();
+                    // Checked against the declared length, before any of the
+                    // payload is buffered.
+                    let header = parse_element_header(&line)?;
+                    let pending = match header {
+                        ElementHeader::Bulk(len) => len + 2,
+                        ElementHeader::Value(_) => 0,
+                    };
+                    if current.size + pending > *max_request_bytes {
+                        return Err(request_too_big());
+                    }
+                    match header {
+                        ElementHeader::Bulk(len) => current.bulk_len = Some(len),
+                        ElementHeader::Value(value) => current.elements.push(value),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn non_ascii_element_header_is_reported_not_panicked() {
+        let _state = shared();
+        let mut decoder = Decoder::new();
+        assert!(decoder.feed(b"*1\r\n\xc3\xa9\r\n").is_empty());
+        let err = decoder.take_error().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn header_line_without_newline_is_bounded() {
+        let _state = shared();
+        let mut decoder = Decoder::new();
+        assert!(decoder.feed(&[b'*'; MAX_LINE_LEN - 1]).is_empty());
+        assert!(decoder.take_error().is_none());
+
+        decoder.feed(b"1");
+        let err = decoder.take_error().unwrap();
+        assert_eq!(err.to_string(), "Protocol error: too big inline request");
+    }
+
+    #[test]
+    fn request_larger_than_the_budget_is_rejected() {
+        let _state = shared();
+        let header = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n";
+
+        let mut decoder = Decoder::new().with_max_request_bytes(64);
+        decoder.feed(header);
+        decoder.feed(b"$40\r\n");
+        let err = decoder.take_error().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Protocol error: request exceeds the size limit"
+        );
+
+        let mut decoder = Decoder::new().with_max_request_bytes(64);
+        decoder.feed(header);
+        let frames = decoder.feed(b"$37\r\n0123456789012345678901234567890123456\r\n");
+        assert!(decoder.take_error().is_none());
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn many_small_elements_count_against_the_budget() {
+        let _state = shared();
+        let mut decoder = Decoder::new().with_max_request_bytes(64);
+        decoder.feed(b"*1000\r\n");
+        for _ in 0..14 {
+            decoder.feed(b":1\r\n");
+        }
+        assert!(decoder.take_error().is_none());
+        decoder.feed(b":1\r\n");
+        assert!(decoder.take_error().is_some());
+    }
+
+    #[test]
+    fn frames_fed_byte_by_byte_emerge_at_their_last_byte() {
+        let _state = shared();
+        let first = marshal(&command(&["SET", "k", "a\r\nb"]));
+        let second = marshal(&command(&["GET", "k"]));
+        let input = [first.as_slice(), b"PING\r\n", second.as_slice()].concat();
+        let ends = [first.len(), first.len() + 6, input.len()];
+
+        let mut decoder = Decoder::new();
+        let mut frames = Vec::new();
+        for (i, byte) in input.iter().enumerate() {
+            let out = decoder.feed(std::slice::from_ref(byte));
+            assert_eq!(!out.is_empty(), ends.contains(&(i + 1)), "byte {}", i);
+            assert_eq!(decoder.is_empty(), ends.contains(&(i + 1)), "byte {}", i);
+            frames.extend(out);
+        }
+        assert!(decoder.take_error().is_none());
+        assert_eq!(
+            frames,
+            vec![
+                command(&["SET", "k", "a\r\nb"]),
+                command(&["PING"]),
+                command(&["GET", "k"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_bulk_length_is_not_allocated_up_front() {
+        let _state = shared();
+        let mut decoder = Decoder::new();
+        decoder.feed(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$536870000\r\nabc");
+        assert!(decoder.take_error().is_none());
+        assert!(decoder.buf.capacity() < 1024, "{}", decoder.buf.capacity());
+
+        decoder.feed(&[b'x'; 4096]);
+        assert!(
+            decoder.buf.capacity() < 16 * 1024,
+            "{}",
+            decoder.buf.capacity()
+        );
+    }
+
+    #[test]
+    fn bulk_payload_split_across_feeds_is_reassembled() {
+        let _state = shared();
+        let value = "v".repeat(100_000);
+        let wire = marshal(&command(&["SET", "k", &value]));
+
+        let mut decoder = Decoder::new();
+        let mut frames = Vec::new();
+        for chunk in wire.chunks(7_000) {
+            frames.extend(decoder.feed(chunk));
+        }
+        assert_eq!(frames, vec![command(&["SET", "k", &value])]);
+        assert!(decoder.is_empty());
+    }
+
+    fn pipelined_sets(count: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|i| marshal(&command(&["SET", &format!("key:{}", i), "value"])))
+            .collect()
+    }
+
+    #[test]
+    fn pipelined_small_commands_are_not_copied_per_argument() {
+        let _state = shared();
+        let input = pipelined_sets(10_000);
+
+        let mut decoder = Decoder::new();
+        let before = allocated_bytes();
+        let frames = decoder.feed(&input);
+        let allocated = allocated_bytes() - before;
+
+        assert_eq!(frames.len(), 10_000);
+        assert_eq!(frames[9_999], command(&["SET", "key:9999", "value"]));
+        // Splitting the buffer per argument would copy the remaining input
+        // 30,000 times, several gigabytes here.
+        assert!(
+            allocated < 20 * input.len() as u64,
+            "{} bytes allocated for {} bytes of input",
+            allocated,
+            input.len()
+        );
+    }
+
+    #[test]
+    #[ignore = "benchmark; run with `cargo test --release -- --ignored --nocapture`"]
+    fn bench_pipelined_small_commands() {
+        let input = pipelined_sets(100_000);
+        let mut decoder = Decoder::new();
+
+        let start = Instant::now();
+        let frames = decoder.feed(&input);
+        let elapsed = start.elapsed();
+
+        assert_eq!(frames.len(), 100_000);
+        println!(
+            "decoded {} pipelined SETs in {:?} ({:.0}/s)",
+            frames.len(),
+            elapsed,
+            frames.len() as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
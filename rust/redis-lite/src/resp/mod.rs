@@ -1,4 +1,5 @@
-use crate::prelude::*;
-
+pub mod decoder;
+#[allow(clippy::module_inception)]
 pub mod resp;
+pub use decoder::*;
 pub use resp::*;
@@ -4,28 +4,243 @@ use crate::prelude::*;
 pub enum RespValue {
     SimpleString(String),
     Error(String),
-    Integer(i32),
+    Integer(i64),
     BulkString(Option<String>),
     Array(Vec<RespValue>),
     Null,
 }
 
+// Extractors return None when the value is not of the requested kind.
+impl RespValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            RespValue::BulkString(Some(s)) | RespValue::SimpleString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.as_str().map(str::as_bytes)
+    }
+
+    // Request arguments arrive as bulk strings, so numeric text counts too.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            RespValue::Integer(i) => Some(*i),
+            _ => self.as_str()?.parse().ok(),
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[RespValue]> {
+        match self {
+            RespValue::Array(arr) => Some(arr),
+            _ => None,
+        }
+    }
+
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            RespValue::BulkString(Some(s)) | RespValue::SimpleString(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+pub const OK_REPLY: &[u8] = b"+OK\r\n";
+pub const PONG_REPLY: &[u8] = b"+PONG\r\n";
+pub const NULL_REPLY: &[u8] = b"$-1\r\n";
+
 pub fn marshal(value: &RespValue) -> Vec<u8> {
+    let mut buf = Vec::new();
+    marshal_to(value, &mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+pub fn marshal_to<W: Write>(value: &RespValue, writer: &mut W) -> std::io::Result<()> {
     match value {
-        RespValue::SimpleString(s) => format!("+{}\r\n", s).into_bytes(),
-        RespValue::Error(s) => format!("-{}\r\n", s).into_bytes(),
-        RespValue::Integer(i) => format!(":{}\r\n", i).into_bytes(),
-        RespValue::BulkString(Some(s)) => format!("${}\r\n{}\r\n", s.len(), s).into_bytes(),
-        RespValue::BulkString(None) => b"$-1\r\n".to_vec(),
+        RespValue::SimpleString(s) => match s.as_str() {
+            "OK" => writer.write_all(OK_REPLY),
+            "PONG" => writer.write_all(PONG_REPLY),
+            _ => write!(writer, "+{}\r\n", s),
+        },
+        RespValue::Error(s) => write!(writer, "-{}\r\n", s),
+        RespValue::Integer(i) => write!(writer, ":{}\r\n", i),
+        RespValue::BulkString(Some(s)) => {
+            write!(writer, "${}\r\n", s.len())?;
+            writer.write_all(s.as_bytes())?;
+            writer.write_all(b"\r\n")
+        }
+        RespValue::BulkString(None) => writer.write_all(NULL_REPLY),
         RespValue::Array(arr) => {
-            let mut buf = format!("*{}\r\n", arr.len()).into_bytes();
+            write!(writer, "*{}\r\n", arr.len())?;
             for item in arr {
-                buf.extend(marshal(item));
+                marshal_to(item, writer)?;
+            }
+            Ok(())
+        }
+        RespValue::Null => writer.write_all(NULL_REPLY),
+    }
+}
+
+// Renders a value the way redis-cli prints replies. Unlike marshal this is
+// meant for people, not for the wire.
+pub fn format_resp(value: &RespValue) -> String {
+    let mut out = String::new();
+    format_into(value, 0, &mut out);
+    out
+}
+
+fn format_into(value: &RespValue, indent: usize, out: &mut String) {
+    match value {
+        RespValue::SimpleString(s) => out.push_str(s),
+        RespValue::Error(e) => out.push_str(&format!("(error) {}", e)),
+        RespValue::Integer(i) => out.push_str(&format!("(integer) {}", i)),
+        RespValue::BulkString(Some(s)) => out.push_str(&format!("{:?}", s)),
+        RespValue::BulkString(None) | RespValue::Null => out.push_str("(nil)"),
+        RespValue::Array(arr) if arr.is_empty() => out.push_str("(empty array)"),
+        RespValue::Array(arr) => {
+            let width = arr.len().to_string().len();
+            for (i, item) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+                let label = format!("{:>width$}) ", i + 1);
+                out.push_str(&label);
+                format_into(item, indent + label.len(), out);
+            }
+        }
+    }
+}
+
+// Same default as Redis' proto-max-bulk-len.
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+pub static STRICT_CRLF: AtomicBool = AtomicBool::new(true);
+pub static LENIENT_RESP: AtomicBool = AtomicBool::new(false);
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+pub(crate) fn check_line_terminator(line: &str) -> std::io::Result<()> {
+    if STRICT_CRLF.load(Ordering::Relaxed) && !line.ends_with("\r\n") {
+        return Err(invalid_data(
+            "Protocol error: expected CRLF line terminator",
+        ));
+    }
+    Ok(())
+}
+
+fn read_protocol_line<R: BufRead>(reader: &mut R, line: &mut String) -> std::io::Result<()> {
+    line.clear();
+    reader.read_line(line)?;
+    if !line.ends_with('\n') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "EOF",
+        ));
+    }
+    check_line_terminator(line)
+}
+
+fn parse_len(line: &str) -> std::io::Result<usize> {
+    line[1..]
+        .trim()
+        .parse()
+        .map_err(|_| invalid_data("Protocol error: invalid length"))
+}
+
+pub(crate) fn parse_array_header(line: &str) -> std::io::Result<usize> {
+    if !line.starts_with('*') {
+        return Err(invalid_data("Not a RESP array"));
+    }
+    parse_len(line)
+}
+
+pub(crate) enum ElementHeader {
+    Bulk(usize),
+    Value(RespValue),
+}
+
+// Integer and simple string elements are rare in requests but valid RESP;
+// handlers only deal in bulk strings, so convert them here.
+pub(crate) fn parse_element_header(line: &str) -> std::io::Result<ElementHeader> {
+    // The type byte is checked before slicing past it: on a line starting with
+    // a multi-byte character, index 1 is not a char boundary.
+    let content = || {
+        line.get(1..)
+            .unwrap_or_default()
+            .trim_end_matches(['\r', '\n'])
+    };
+    match line.as_bytes().first() {
+        Some(b'$') => {
+            let len = parse_len(line)?;
+            if len > MAX_BULK_LEN {
+                return Err(invalid_data("Protocol error: invalid bulk length"));
+            }
+            Ok(ElementHeader::Bulk(len))
+        }
+        Some(b':') => {
+            let i: i64 = content()
+                .parse()
+                .map_err(|_| invalid_data("Protocol error: invalid integer"))?;
+            Ok(ElementHeader::Value(RespValue::BulkString(Some(
+                i.to_string(),
+            ))))
+        }
+        Some(b'+') => Ok(ElementHeader::Value(RespValue::BulkString(Some(
+            content().to_string(),
+        )))),
+        _ => Err(invalid_data("Expected bulk string")),
+    }
+}
+
+pub(crate) fn check_bulk_terminator(line: &str) -> std::io::Result<()> {
+    if !line.trim_end_matches(['\r', '\n']).is_empty() {
+        return Err(invalid_data("Protocol error: bulk string length mismatch"));
+    }
+    Ok(())
+}
+
+// Some homegrown clients put a whole multibulk on one space-separated line,
+// e.g. `*2 $3 foo $3 bar`. Only accepted when LENIENT_RESP is on.
+pub(crate) fn parse_lenient_line(line: &str) -> std::io::Result<Option<RespValue>> {
+    if !LENIENT_RESP.load(Ordering::Relaxed) || !line.trim_end().contains(' ') {
+        return Ok(None);
+    }
+
+    let mut tokens = line.split_whitespace();
+    let count = parse_array_header(tokens.next().unwrap_or_default())?;
+    let mut next = || {
+        tokens
+            .next()
+            .ok_or_else(|| invalid_data("Protocol error: lenient request is too short"))
+    };
+
+    let mut elements = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        match parse_element_header(next()?)? {
+            ElementHeader::Bulk(len) => {
+                let value = next()?;
+                if value.len() != len {
+                    return Err(invalid_data("Protocol error: bulk string length mismatch"));
+                }
+                elements.push(RespValue::BulkString(Some(value.to_string())));
             }
-            buf
+            ElementHeader::Value(value) => elements.push(value),
         }
-        RespValue::Null => b"$-1\r\n".to_vec(),
     }
+    if tokens.next().is_some() {
+        return Err(invalid_data("Protocol error: lenient request is too long"));
+    }
+
+    Ok(Some(RespValue::Array(elements)))
+}
+
+// Valid UTF-8 reuses the read buffer; only invalid input is copied.
+pub(crate) fn bulk_to_string(buf: Vec<u8>) -> String {
+    String::from_utf8(buf).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
 }
 
 pub fn read_resp<R: BufRead>(reader: &mut R) -> Result<RespValue, std::io::Error> {
@@ -33,45 +248,237 @@ pub fn read_resp<R: BufRead>(reader: &mut R) -> Result<RespValue, std::io::Error
 
     // Skip empty or whitespace-only lines
     loop {
-        line.clear();
-        let bytes_read = reader.read_line(&mut line)?;
-        if bytes_read == 0 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "EOF",
-            ));
-        }
+        read_protocol_line(reader, &mut line)?;
         if !line.trim().is_empty() {
             break;
         }
     }
 
-    if !line.starts_with('*') {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Not a RESP array",
-        ));
+    if let Some(command) = parse_lenient_line(&line)? {
+        return Ok(command);
     }
-    let array_len: usize = line[1..].trim().parse().unwrap();
-    let mut elements = Vec::with_capacity(array_len);
+
+    let array_len = parse_array_header(&line)?;
+    // The length comes from the client, so don't trust it for preallocation.
+    let mut elements = Vec::with_capacity(array_len.min(1024));
 
     for _ in 0..array_len {
-        line.clear();
-        reader.read_line(&mut line)?;
-        if !line.starts_with('$') {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Expected bulk string",
-            ));
-        }
+        read_protocol_line(reader, &mut line)?;
+        let str_len = match parse_element_header(&line)? {
+            ElementHeader::Bulk(len) => len,
+            ElementHeader::Value(value) => {
+                elements.push(value);
+                continue;
+            }
+        };
 
-        let str_len: usize = line[1..].trim().parse().unwrap();
-        let mut buf = vec![0; str_len + 2];
+        let mut buf = vec![0; str_len];
         reader.read_exact(&mut buf)?;
 
-        let s = String::from_utf8_lossy(&buf[..str_len]).to_string();
-        elements.push(RespValue::BulkString(Some(s)));
+        read_protocol_line(reader, &mut line)?;
+        check_bulk_terminator(&line)?;
+
+        elements.push(RespValue::BulkString(Some(bulk_to_string(buf))));
     }
 
     Ok(RespValue::Array(elements))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn marshal_to_streams_a_large_array() {
+        let items: Vec<String> = (0..10_000).map(|i| format!("key:{}", i)).collect();
+        let value = RespValue::Array(items.iter().map(|s| bulk(s)).collect());
+
+        let mut expected = format!("*{}\r\n", items.len()).into_bytes();
+        for item in &items {
+            expected.extend(format!("${}\r\n{}\r\n", item.len(), item).into_bytes());
+        }
+
+        let mut out = Vec::new();
+        marshal_to(&value, &mut out).unwrap();
+        assert_eq!(out, expected);
+        assert_eq!(read_resp(&mut out.as_slice()).unwrap(), value);
+    }
+
+    #[test]
+    fn strict_mode_rejects_bare_lf_framing() {
+        let _state = shared();
+        let err = read_resp(&mut &b"*1\n$4\nPING\n"[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn relaxed_mode_accepts_bare_lf_framing() {
+        let _state = exclusive();
+        STRICT_CRLF.store(false, Ordering::Relaxed);
+        let parsed = read_resp(&mut &b"*1\n$4\nPING\n"[..]);
+        STRICT_CRLF.store(true, Ordering::Relaxed);
+        assert_eq!(parsed.unwrap(), RespValue::Array(vec![bulk("PING")]));
+    }
+
+    #[test]
+    fn integer_and_simple_string_elements_become_bulk_strings() {
+        let _state = shared();
+        let parsed = read_resp(&mut &b"*3\r\n$3\r\nSET\r\n+key\r\n:42\r\n"[..]).unwrap();
+        assert_eq!(
+            parsed,
+            RespValue::Array(vec![bulk("SET"), bulk("key"), bulk("42")])
+        );
+    }
+
+    #[test]
+    fn non_ascii_element_header_is_a_protocol_error() {
+        let _state = shared();
+        for input in [&b"*1\r\n\xff\r\n"[..], &b"*1\r\n\xc3\xa9\r\n"[..]] {
+            let err = read_resp(&mut &input[..]).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+        assert!(parse_element_header("é\r\n").is_err());
+        assert!(parse_element_header("\u{fffd}\r\n").is_err());
+    }
+
+    #[test]
+    fn non_ascii_lenient_element_is_a_protocol_error() {
+        let _state = exclusive();
+        LENIENT_RESP.store(true, Ordering::Relaxed);
+        let parsed = parse_lenient_line("*1 é\r\n");
+        LENIENT_RESP.store(false, Ordering::Relaxed);
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn hot_replies_are_encoded_without_allocating() {
+        let ok = RespValue::SimpleString("OK".to_string());
+        let pong = RespValue::SimpleString("PONG".to_string());
+        let mut out = Vec::with_capacity(64);
+
+        let before = allocations();
+        for value in [&ok, &pong, &RespValue::Null, &RespValue::BulkString(None)] {
+            marshal_to(value, &mut out).unwrap();
+        }
+        assert_eq!(allocations(), before);
+        assert_eq!(out, b"+OK\r\n+PONG\r\n$-1\r\n$-1\r\n");
+    }
+
+    #[test]
+    #[ignore = "benchmark; run with `cargo test --release -- --ignored --nocapture`"]
+    fn bench_set_get_reply_allocations() {
+        const OPS: u64 = 100_000;
+        let _state = shared();
+        let mut out = Vec::with_capacity(1024);
+
+        let (mut encoding, mut total) = (0, 0);
+        let start = Instant::now();
+        for i in 0..OPS {
+            let key = format!("bench:{}", i % 100);
+            let before = allocations();
+            let set = run(&["SET", &key, "value"]);
+            let get = run(&["GET", &format!("{}:missing", key)]);
+            let encode_from = allocations();
+            out.clear();
+            marshal_to(&set, &mut out).unwrap();
+            marshal_to(&get, &mut out).unwrap();
+            encoding += allocations() - encode_from;
+            total += allocations() - before;
+        }
+
+        println!(
+            "SET/GET: {:.0} ops/s, {:.1} allocations per op, {} while encoding replies",
+            OPS as f64 / start.elapsed().as_secs_f64(),
+            total as f64 / OPS as f64,
+            encoding
+        );
+    }
+
+    #[test]
+    fn format_resp_renders_each_scalar_like_redis_cli() {
+        let cases = [
+            (RespValue::SimpleString("OK".to_string()), "OK"),
+            (RespValue::Error("ERR bad".to_string()), "(error) ERR bad"),
+            (RespValue::Integer(-7), "(integer) -7"),
+            (bulk("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\""),
+            (RespValue::BulkString(None), "(nil)"),
+            (RespValue::Null, "(nil)"),
+            (RespValue::Array(vec![]), "(empty array)"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(format_resp(&value), expected);
+        }
+    }
+
+    #[test]
+    fn format_resp_indents_nested_arrays() {
+        let nested = RespValue::Array(vec![
+            bulk("a"),
+            RespValue::Array(vec![RespValue::Integer(1), RespValue::Null]),
+            RespValue::Array(vec![]),
+        ]);
+        assert_eq!(
+            format_resp(&nested),
+            "1) \"a\"\n2) 1) (integer) 1\n   2) (nil)\n3) (empty array)"
+        );
+
+        let wide = RespValue::Array((0..10).map(RespValue::Integer).collect());
+        let rendered = format_resp(&wide);
+        assert!(rendered.starts_with(" 1) (integer) 0\n 2) (integer) 1\n"));
+        assert!(rendered.ends_with("\n10) (integer) 9"));
+    }
+
+    #[test]
+    fn space_separated_request_is_parsed_in_lenient_mode() {
+        let _state = exclusive();
+        let line = b"*3 $3 SET $3 foo $2 42\r\n";
+        let strict = read_resp(&mut std::io::Cursor::new(line));
+
+        LENIENT_RESP.store(true, Ordering::Relaxed);
+        let lenient = read_resp(&mut std::io::Cursor::new(line));
+        let decoded = Decoder::new().feed(line);
+        let mismatch = read_resp(&mut std::io::Cursor::new(b"*1 $4 foo\r\n"));
+        let short = read_resp(&mut std::io::Cursor::new(b"*2 $3 foo\r\n"));
+        LENIENT_RESP.store(false, Ordering::Relaxed);
+
+        let expected = command(&["SET", "foo", "42"]);
+        assert!(strict.is_err());
+        assert_eq!(lenient.unwrap(), expected);
+        assert_eq!(decoded, vec![expected]);
+        assert!(mismatch.is_err());
+        assert!(short.is_err());
+    }
+
+    #[test]
+    fn accessors_extract_matching_variants_only() {
+        let bulk_value = bulk("42");
+        let simple = RespValue::SimpleString("OK".to_string());
+        let integer = RespValue::Integer(7);
+        let array = RespValue::Array(vec![bulk("a")]);
+        let error = RespValue::Error("ERR x".to_string());
+
+        assert_eq!(bulk_value.as_str(), Some("42"));
+        assert_eq!(simple.as_str(), Some("OK"));
+        assert_eq!(integer.as_str(), None);
+        assert_eq!(error.as_str(), None);
+        assert_eq!(RespValue::BulkString(None).as_str(), None);
+
+        assert_eq!(bulk_value.as_bytes(), Some(&b"42"[..]));
+        assert_eq!(RespValue::Null.as_bytes(), None);
+
+        assert_eq!(bulk_value.as_i64(), Some(42));
+        assert_eq!(integer.as_i64(), Some(7));
+        assert_eq!(simple.as_i64(), None);
+        assert_eq!(array.as_i64(), None);
+
+        assert_eq!(array.as_array(), Some(&[bulk("a")][..]));
+        assert_eq!(bulk_value.as_array(), None);
+
+        assert_eq!(bulk_value.into_string(), Some("42".to_string()));
+        assert_eq!(simple.into_string(), Some("OK".to_string()));
+        assert_eq!(integer.into_string(), None);
+        assert_eq!(error.into_string(), None);
+        assert_eq!(RespValue::Null.into_string(), None);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::prelude::*;
+
+// Features a client might probe for. Ones this build has no support for at
+// all are listed too, so clients can tell "off" apart from "unknown".
+fn features() -> Vec<(&'static str, bool)> {
+    vec![
+        ("resp2", true),
+        ("resp3", false),
+        ("aof", aof_enabled()),
+        ("debug", ENABLE_DEBUG.load(Ordering::Relaxed)),
+        ("read-only", READ_ONLY.load(Ordering::Relaxed)),
+        ("lenient-resp", LENIENT_RESP.load(Ordering::Relaxed)),
+        ("tls", false),
+        ("replication", false),
+        ("pubsub", false),
+        ("streams", false),
+        ("scripting", false),
+    ]
+}
+
+pub fn capabilities(_args: Vec<RespValue>) -> RespValue {
+    let mut reply = Vec::new();
+    for (name, enabled) in features() {
+        reply.push(RespValue::BulkString(Some(name.to_string())));
+        reply.push(RespValue::BulkString(Some(format_bool(enabled))));
+    }
+
+    RespValue::Array(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    fn reported() -> Vec<(String, String)> {
+        let reply = run(&["CAPABILITIES"]);
+        let items = reply.as_array().unwrap();
+        items
+            .chunks(2)
+            .map(|pair| {
+                let name = pair[0].as_str().unwrap().to_string();
+                (name, pair[1].as_str().unwrap().to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn capabilities_lists_enabled_features() {
+        let _state = exclusive();
+        ENABLE_DEBUG.store(true, Ordering::Relaxed);
+        LENIENT_RESP.store(true, Ordering::Relaxed);
+        let enabled = reported();
+        ENABLE_DEBUG.store(false, Ordering::Relaxed);
+        LENIENT_RESP.store(false, Ordering::Relaxed);
+        let disabled = reported();
+
+        let value = |features: &[(String, String)], name: &str| {
+            features
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+        assert_eq!(value(&enabled, "resp2").as_deref(), Some("yes"));
+        assert_eq!(value(&enabled, "debug").as_deref(), Some("yes"));
+        assert_eq!(value(&enabled, "lenient-resp").as_deref(), Some("yes"));
+        assert_eq!(value(&enabled, "read-only").as_deref(), Some("no"));
+        assert_eq!(value(&enabled, "tls").as_deref(), Some("no"));
+        assert_eq!(value(&disabled, "debug").as_deref(), Some("no"));
+        assert_eq!(value(&disabled, "lenient-resp").as_deref(), Some("no"));
+        assert_eq!(value(&disabled, "aof"), Some(format_bool(aof_enabled())));
+
+        let names: Vec<_> = enabled.iter().map(|(n, _)| n.as_str()).collect();
+        let expected: Vec<_> = features().iter().map(|(n, _)| *n).collect();
+        assert_eq!(names, expected);
+    }
+}
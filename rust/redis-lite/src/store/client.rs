@@ -0,0 +1,238 @@
+use crate::prelude::*;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub struct ConnCtx {
+    pub id: u64,
+    pub peer: String,
+    propagation: RefCell<Option<Vec<RespValue>>>,
+}
+
+impl ConnCtx {
+    pub fn new(peer: String) -> Self {
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            peer,
+            propagation: RefCell::new(None),
+        }
+    }
+
+    // Context for commands issued by the server itself, such as AOF replay.
+    pub fn internal() -> Self {
+        Self {
+            id: 0,
+            peer: "internal".to_string(),
+            propagation: RefCell::new(None),
+        }
+    }
+
+    // Lets a handler journal commands that describe its effect instead of the
+    // command the client sent. Calling it with nothing journals nothing.
+    pub fn propagate(&self, command: RespValue) {
+        self.propagation
+            .borrow_mut()
+            .get_or_insert_with(Vec::new)
+            .push(command);
+    }
+
+    pub fn suppress_propagation(&self) {
+        self.propagation.borrow_mut().get_or_insert_with(Vec::new);
+    }
+
+    pub fn take_propagation(&self) -> Option<Vec<RespValue>> {
+        self.propagation.borrow_mut().take()
+    }
+}
+
+struct Pause {
+    until: Option<Instant>,
+    writes_only: bool,
+}
+
+static PAUSE: Lazy<(Mutex<Pause>, Condvar)> = Lazy::new(|| {
+    (
+        Mutex::new(Pause {
+            until: None,
+            writes_only: false,
+        }),
+        Condvar::new(),
+    )
+});
+
+pub fn pause_clients(duration: Duration, writes_only: bool) {
+    let (lock, cvar) = &*PAUSE;
+    let mut pause = lock.lock().unwrap();
+    let until = Instant::now() + duration;
+
+    let active = pause.until.is_some_and(|t| t > Instant::now());
+    if active {
+        pause.until = pause.until.max(Some(until));
+        pause.writes_only = pause.writes_only && writes_only;
+    } else {
+        pause.until = Some(until);
+        pause.writes_only = writes_only;
+    }
+    cvar.notify_all();
+}
+
+pub fn unpause_clients() {
+    let (lock, cvar) = &*PAUSE;
+    lock.lock().unwrap().until = None;
+    cvar.notify_all();
+}
+
+pub fn wait_while_paused(is_write: bool) {
+    let (lock, cvar) = &*PAUSE;
+    let mut pause = lock.lock().unwrap();
+    loop {
+        let until = match pause.until {
+            Some(until) if !pause.writes_only || is_write => until,
+            _ => return,
+        };
+        let now = Instant::now();
+        if now >= until {
+            return;
+        }
+        pause = cvar.wait_timeout(pause, until - now).unwrap().0;
+    }
+}
+
+fn client_pause(args: &[RespValue]) -> RespValue {
+    let millis = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => match s.parse::<u64>() {
+            Ok(ms) => ms,
+            Err(_) => {
+                return RespValue::Error(
+                    "ERR timeout is not an integer or out of range".to_string(),
+                );
+            }
+        },
+        _ => return RespValue::Error("ERR CLIENT PAUSE requires a timeout".to_string()),
+    };
+
+    let writes_only = match args.get(1) {
+        None => false,
+        Some(RespValue::BulkString(Some(mode))) => match mode.to_lowercase().as_str() {
+            "all" => false,
+            "write" => true,
+            _ => return RespValue::Error("ERR syntax error".to_string()),
+        },
+        Some(_) => return RespValue::Error("ERR syntax error".to_string()),
+    };
+
+    pause_clients(Duration::from_millis(millis), writes_only);
+    RespValue::SimpleString("OK".to_string())
+}
+
+pub fn hello(args: Vec<RespValue>, ctx: &ConnCtx) -> RespValue {
+    match args.first() {
+        None => {}
+        Some(RespValue::BulkString(Some(v))) if v == "2" => {}
+        Some(RespValue::BulkString(Some(v))) if v.parse::<i64>().is_ok() => {
+            return RespValue::Error(
+                "NOPROTO sorry, this protocol version is not supported".to_string(),
+            );
+        }
+        Some(_) => {
+            return RespValue::Error(
+                "ERR Protocol version is not an integer or out of range".to_string(),
+            );
+        }
+    }
+    if args.len() > 1 {
+        return RespValue::Error("ERR syntax error".to_string());
+    }
+
+    let field = |name: &str| RespValue::BulkString(Some(name.to_string()));
+    RespValue::Array(vec![
+        field("server"),
+        field("redis"),
+        field("version"),
+        field(env!("CARGO_PKG_VERSION")),
+        field("proto"),
+        RespValue::Integer(2),
+        field("id"),
+        RespValue::Integer(ctx.id as i64),
+        field("mode"),
+        field("standalone"),
+        field("role"),
+        field("master"),
+        field("modules"),
+        RespValue::Array(vec![]),
+    ])
+}
+
+pub fn client(args: Vec<RespValue>) -> RespValue {
+    let sub = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
+        _ => return RespValue::Error("ERR CLIENT requires a subcommand".to_string()),
+    };
+
+    match sub.as_str() {
+        "pause" => client_pause(&args[1..]),
+        "unpause" => {
+            unpause_clients();
+            RespValue::SimpleString("OK".to_string())
+        }
+        _ => RespValue::Error("ERR Invalid CLIENT subcommand".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    fn timed_get() -> Duration {
+        thread::spawn(|| {
+            let start = Instant::now();
+            run(&["GET", "pause:k"]);
+            start.elapsed()
+        })
+        .join()
+        .unwrap()
+    }
+
+    #[test]
+    fn client_pause_all_delays_reads() {
+        let _state = exclusive();
+        assert_eq!(
+            run(&["CLIENT", "PAUSE", "200", "ALL"]),
+            RespValue::SimpleString("OK".to_string())
+        );
+        let waited = timed_get();
+        unpause_clients();
+        assert!(waited >= Duration::from_millis(150), "{:?}", waited);
+    }
+
+    #[test]
+    fn client_pause_write_lets_reads_through() {
+        let _state = exclusive();
+        run(&["CLIENT", "PAUSE", "2000", "WRITE"]);
+        let waited = timed_get();
+        run(&["CLIENT", "UNPAUSE"]);
+        assert!(waited < Duration::from_millis(1000), "{:?}", waited);
+    }
+
+    #[test]
+    fn hello_reports_proto_and_role() {
+        let ctx = ConnCtx::new("test".to_string());
+        let RespValue::Array(fields) = hello(vec![], &ctx) else {
+            panic!("HELLO should reply with a flat map");
+        };
+        let field = |name: &str| {
+            fields
+                .chunks(2)
+                .find(|pair| pair[0] == bulk(name))
+                .map(|pair| pair[1].clone())
+        };
+
+        assert_eq!(field("proto"), Some(RespValue::Integer(2)));
+        assert_eq!(field("role"), Some(bulk("master")));
+        assert_eq!(field("id"), Some(RespValue::Integer(ctx.id as i64)));
+        assert_eq!(
+            hello(vec![bulk("3")], &ctx),
+            RespValue::Error("NOPROTO sorry, this protocol version is not supported".to_string())
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use crate::prelude::*;
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub arity: i32,
+    pub flags: &'static [&'static str],
+    pub first_key: i32,
+    pub last_key: i32,
+    pub step: i32,
+}
+
+impl CommandSpec {
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    // Same convention as Redis: a positive arity is exact, a negative one is
+    // a minimum. Both count the command name itself.
+    pub fn accepts_argc(&self, argc: usize) -> bool {
+        let argc = argc as i32;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    fn info(&self) -> RespValue {
+        RespValue::Array(vec![
+            RespValue::BulkString(Some(self.name.to_string())),
+            RespValue::Integer(self.arity.into()),
+            RespValue::Array(
+                self.flags
+                    .iter()
+                    .map(|f| RespValue::SimpleString(f.to_string()))
+                    .collect(),
+            ),
+            RespValue::Integer(self.first_key.into()),
+            RespValue::Integer(self.last_key.into()),
+            RespValue::Integer(self.step.into()),
+        ])
+    }
+}
+
+pub static COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "asking",
+        arity: 1,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "capabilities",
+        arity: 1,
+        flags: &["loading", "stale", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "client",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "command",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "config",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "cset",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+    },
+    CommandSpec {
+        name: "debug",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "del",
+        arity: -2,
+        flags: &["write"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+    },
+    CommandSpec {
+        name: "delmatch",
+        arity: 2,
+        flags: &["write"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "get",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+    },
+    CommandSpec {
+        name: "getrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+    },
+    CommandSpec {
+        name: "hello",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "info",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "keys",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "memory",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "mset",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+    },
+    CommandSpec {
+        name: "object",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+    },
+    CommandSpec {
+        name: "ping",
+        arity: -1,
+        flags: &["fast", "loading"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "readonly",
+        arity: 1,
+        flags: &["fast", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "readwrite",
+        arity: 1,
+        flags: &["fast", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+    },
+    CommandSpec {
+        name: "set",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+    },
+];
+
+// Per-command counters are indexed by position in COMMANDS.
+pub fn command_index(name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    COMMANDS.iter().position(|spec| spec.name == name)
+}
+
+pub fn lookup_command(name: &str) -> Option<&'static CommandSpec> {
+    command_index(name).map(|i| &COMMANDS[i])
+}
+
+// Argument positions that hold keys, per the spec's first/last/step.
+fn key_positions(arr: &[RespValue]) -> Vec<usize> {
+    let spec = match arr.first() {
+        Some(RespValue::BulkString(Some(name))) => lookup_command(name),
+        _ => None,
+    };
+    let spec = match spec {
+        Some(spec) if spec.first_key > 0 && spec.step > 0 => spec,
+        _ => return Vec::new(),
+    };
+
+    let last = if spec.last_key < 0 {
+        arr.len() as i32 + spec.last_key
+    } else {
+        spec.last_key.min(arr.len() as i32 - 1)
+    };
+
+    (spec.first_key..=last)
+        .step_by(spec.step as usize)
+        .map(|i| i as usize)
+        .collect()
+}
+
+pub fn command_keys(command: &RespValue) -> Vec<&str> {
+    let arr = match command {
+        RespValue::Array(arr) => arr,
+        _ => return Vec::new(),
+    };
+    key_positions(arr)
+        .into_iter()
+        .filter_map(|i| match arr.get(i) {
+            Some(RespValue::BulkString(Some(key))) => Some(key.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+// The arguments that are not keys, such as the values a write stores.
+pub fn command_values(command: &RespValue) -> Vec<&str> {
+    let arr = match command {
+        RespValue::Array(arr) => arr,
+        _ => return Vec::new(),
+    };
+    let keys = key_positions(arr);
+    arr.iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(i, _)| !keys.contains(i))
+        .filter_map(|(_, arg)| match arg {
+            RespValue::BulkString(Some(val)) => Some(val.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn commands(args: Vec<RespValue>) -> RespValue {
+    let sub = match args.first() {
+        None => return RespValue::Array(COMMANDS.iter().map(CommandSpec::info).collect()),
+        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
+        _ => return RespValue::Error("ERR Invalid COMMAND subcommand".to_string()),
+    };
+
+    match sub.as_str() {
+        "count" => RespValue::Integer(COMMANDS.len() as i64),
+        "info" => RespValue::Array(
+            args[1..]
+                .iter()
+                .map(|arg| match arg {
+                    RespValue::BulkString(Some(name)) => match lookup_command(name) {
+                        Some(spec) => spec.info(),
+                        None => RespValue::Null,
+                    },
+                    _ => RespValue::Null,
+                })
+                .collect(),
+        ),
+        _ => RespValue::Error("ERR Invalid COMMAND subcommand".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn command_info_reports_arity_flags_and_keys() {
+        let reply = commands(vec![bulk("INFO"), bulk("get"), bulk("nosuchcommand")]);
+        let RespValue::Array(entries) = reply else {
+            panic!("COMMAND INFO should reply with an array");
+        };
+
+        assert_eq!(
+            entries[0],
+            RespValue::Array(vec![
+                bulk("get"),
+                RespValue::Integer(2),
+                RespValue::Array(vec![
+                    RespValue::SimpleString("readonly".to_string()),
+                    RespValue::SimpleString("fast".to_string()),
+                ]),
+                RespValue::Integer(1),
+                RespValue::Integer(1),
+                RespValue::Integer(1),
+            ])
+        );
+        assert_eq!(entries[1], RespValue::Null);
+    }
+
+    #[test]
+    fn arity_is_exact_when_positive_and_a_minimum_when_negative() {
+        let get = lookup_command("get").unwrap();
+        assert!(get.accepts_argc(2));
+        assert!(!get.accepts_argc(1) && !get.accepts_argc(3));
+
+        let del = lookup_command("del").unwrap();
+        assert!(!del.accepts_argc(1));
+        assert!(del.accepts_argc(2) && del.accepts_argc(100));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::prelude::*;
+
+pub static READ_ONLY: AtomicBool = AtomicBool::new(false);
+pub static ENABLE_DEBUG: AtomicBool = AtomicBool::new(false);
+pub static MAX_STRING_BYTES: AtomicU64 = AtomicU64::new(0);
+pub static MAX_KEY_BYTES: AtomicU64 = AtomicU64::new(0);
+
+struct ConfigParam {
+    name: &'static str,
+    get: fn() -> String,
+    set: fn(&str) -> Result<(), String>,
+}
+
+pub(crate) fn format_bool(b: bool) -> String {
+    if b { "yes" } else { "no" }.to_string()
+}
+
+pub(crate) fn parse_bool(val: &str) -> Result<bool, String> {
+    match val.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".to_string()),
+    }
+}
+
+fn parse_u64(val: &str) -> Result<u64, String> {
+    val.parse()
+        .map_err(|_| "argument must be a non-negative integer".to_string())
+}
+
+static PARAMS: &[ConfigParam] = &[
+    ConfigParam {
+        name: "max-key-bytes",
+        get: || MAX_KEY_BYTES.load(Ordering::Relaxed).to_string(),
+        set: |val| {
+            MAX_KEY_BYTES.store(parse_u64(val)?, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    ConfigParam {
+        name: "max-string-bytes",
+        get: || MAX_STRING_BYTES.load(Ordering::Relaxed).to_string(),
+        set: |val| {
+            MAX_STRING_BYTES.store(parse_u64(val)?, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    ConfigParam {
+        name: "read-only",
+        get: || format_bool(READ_ONLY.load(Ordering::Relaxed)),
+        set: |val| {
+            READ_ONLY.store(parse_bool(val)?, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+];
+
+pub fn is_config_param(name: &str) -> bool {
+    PARAMS.iter().any(|p| p.name == name)
+}
+
+pub fn set_config_param(name: &str, val: &str) -> Result<(), String> {
+    match PARAMS.iter().find(|p| p.name == name) {
+        Some(param) => (param.set)(val),
+        None => Err(format!("unknown parameter '{}'", name)),
+    }
+}
+
+pub fn exceeds_max_string_bytes(len: usize) -> bool {
+    let max = MAX_STRING_BYTES.load(Ordering::Relaxed);
+    max > 0 && len as u64 > max
+}
+
+pub fn exceeds_max_key_bytes(len: usize) -> bool {
+    let max = MAX_KEY_BYTES.load(Ordering::Relaxed);
+    max > 0 && len as u64 > max
+}
+
+fn config_get(args: &[RespValue]) -> RespValue {
+    let name = match args.first() {
+        Some(RespValue::BulkString(Some(s))) if args.len() == 1 => s.to_lowercase(),
+        _ => return RespValue::Error("ERR CONFIG GET requires a parameter".to_string()),
+    };
+
+    let mut reply = Vec::new();
+    for param in PARAMS.iter().filter(|p| glob_match(&name, p.name)) {
+        reply.push(RespValue::BulkString(Some(param.name.to_string())));
+        reply.push(RespValue::BulkString(Some((param.get)())));
+    }
+
+    RespValue::Array(reply)
+}
+
+fn config_set(args: &[RespValue]) -> RespValue {
+    let (name, val) = match args {
+        [
+            RespValue::BulkString(Some(n)),
+            RespValue::BulkString(Some(v)),
+        ] => (n.to_lowercase(), v),
+        _ => {
+            return RespValue::Error("ERR CONFIG SET requires a parameter and a value".to_string());
+        }
+    };
+
+    let param = match PARAMS.iter().find(|p| p.name == name) {
+        Some(p) => p,
+        None => {
+            return RespValue::Error(format!(
+                "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                name
+            ));
+        }
+    };
+
+    match (param.set)(val) {
+        Ok(()) => RespValue::SimpleString("OK".to_string()),
+        Err(e) => RespValue::Error(format!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+            name, e
+        )),
+    }
+}
+
+pub fn config(args: Vec<RespValue>) -> RespValue {
+    let sub = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
+        _ => return RespValue::Error("ERR CONFIG requires a subcommand".to_string()),
+    };
+
+    match sub.as_str() {
+        "get" => config_get(&args[1..]),
+        "set" => config_set(&args[1..]),
+        "resetstat" => {
+            STATS.reset();
+            RespValue::SimpleString("OK".to_string())
+        }
+        _ => RespValue::Error("ERR Invalid CONFIG subcommand".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn writes_past_max_string_bytes_are_rejected() {
+        let _state = exclusive();
+        run(&["CONFIG", "SET", "max-string-bytes", "10"]);
+        let at_limit = run(&["SET", "limit:k", "0123456789"]);
+        let over = run(&["SET", "limit:k", "0123456789x"]);
+        let mset_over = run(&["MSET", "limit:a", "ok", "limit:b", "0123456789x"]);
+        let cset_over = run(&["CSET", "limit:k", "0123456789", "0123456789x"]);
+        run(&["CONFIG", "SET", "max-string-bytes", "0"]);
+
+        let too_big = RespValue::Error("ERR string exceeds maximum allowed size".to_string());
+        assert_eq!(at_limit, RespValue::SimpleString("OK".to_string()));
+        assert_eq!(over, too_big);
+        assert_eq!(mset_over, too_big);
+        assert_eq!(cset_over, too_big);
+        assert_eq!(run(&["GET", "limit:k"]), bulk("0123456789"));
+        assert_eq!(run(&["GET", "limit:a"]), RespValue::Null);
+    }
+
+    #[test]
+    fn replay_keeps_values_stored_before_the_limit_was_lowered() {
+        let _state = exclusive();
+        let dir = temp_dir("max-string-replay");
+        let set = command(&["SET", "limit:replayed", "0123456789x"]);
+        std::fs::write(dir.join("aof.log"), marshal(&set)).unwrap();
+
+        MAX_STRING_BYTES.store(10, Ordering::Relaxed);
+        let summary = Aof::new(dir.to_str().unwrap(), "aof.log", 1)
+            .unwrap()
+            .read();
+        MAX_STRING_BYTES.store(0, Ordering::Relaxed);
+
+        assert_eq!(summary.unwrap().errors, 0);
+        assert_eq!(run(&["GET", "limit:replayed"]), bulk("0123456789x"));
+        run(&["DEL", "limit:replayed"]);
+    }
+
+    #[test]
+    fn keys_past_max_key_bytes_are_rejected() {
+        let _state = exclusive();
+        let previous = MAX_KEY_BYTES.load(Ordering::Relaxed);
+        run(&["CONFIG", "SET", "max-key-bytes", "8"]);
+        let under = run(&["SET", "keylen:1", "v"]);
+        let over = run(&["SET", "keylen:12", "v"]);
+        let mset_over = run(&["MSET", "keylen:2", "v", "keylen:123", "v"]);
+        let read_over = run(&["GET", "keylen:12"]);
+        let setting = run(&["CONFIG", "GET", "max-key-bytes"]);
+        MAX_KEY_BYTES.store(previous, Ordering::Relaxed);
+
+        let too_long = RespValue::Error("ERR key is too long".to_string());
+        assert_eq!(under, RespValue::SimpleString("OK".to_string()));
+        assert_eq!(over, too_long);
+        assert_eq!(mset_over, too_long);
+        assert_eq!(read_over, RespValue::Null);
+        assert_eq!(
+            setting,
+            RespValue::Array(vec![bulk("max-key-bytes"), bulk("8")])
+        );
+        assert_eq!(run(&["GET", "keylen:2"]), RespValue::Null);
+        run(&["DEL", "keylen:1"]);
+    }
+}
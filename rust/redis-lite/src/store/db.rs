@@ -1,81 +1,791 @@
 use crate::prelude::*;
 
-static CACHE: Lazy<RwLock<HashMap<String, RespValue>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+// Ordered so that pattern lookups with a literal prefix can scan a key range.
+type Store = BTreeMap<String, RespValue>;
+
+static CACHE: Lazy<RwLock<Store>> = Lazy::new(|| RwLock::new(BTreeMap::new()));
+
+fn matching_keys<'a>(map: &'a Store, pattern: &'a str) -> impl Iterator<Item = &'a String> {
+    let prefix = literal_prefix(pattern);
+    map.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+        .map(|(key, _)| key)
+        .take_while(move |key| key.starts_with(prefix))
+        .filter(move |key| glob_match(pattern, key))
+}
+
+pub(crate) fn keys_matching(pattern: &str, limit: usize) -> Vec<String> {
+    let map = read_cache();
+    matching_keys(&map, pattern).take(limit).cloned().collect()
+}
+
+#[derive(Default)]
+pub struct LockStats {
+    pub reads: AtomicU64,
+    pub read_waits: AtomicU64,
+    pub writes: AtomicU64,
+    pub write_waits: AtomicU64,
+    pub wait_micros: AtomicU64,
+}
+
+pub static LOCK_STATS: Lazy<LockStats> = Lazy::new(LockStats::default);
+
+// Every store access goes through these two so contention can be counted:
+// a failed try_* means the caller had to wait for another holder.
+pub(crate) fn read_cache() -> std::sync::RwLockReadGuard<'static, Store> {
+    LOCK_STATS.reads.fetch_add(1, Ordering::Relaxed);
+    if let Ok(guard) = CACHE.try_read() {
+        return guard;
+    }
+    LOCK_STATS.read_waits.fetch_add(1, Ordering::Relaxed);
+    let start = Instant::now();
+    let guard = CACHE.read().unwrap();
+    LOCK_STATS
+        .wait_micros
+        .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+    guard
+}
+
+pub(crate) fn write_cache() -> std::sync::RwLockWriteGuard<'static, Store> {
+    LOCK_STATS.writes.fetch_add(1, Ordering::Relaxed);
+    if let Ok(guard) = CACHE.try_write() {
+        return guard;
+    }
+    LOCK_STATS.write_waits.fetch_add(1, Ordering::Relaxed);
+    let start = Instant::now();
+    let guard = CACHE.write().unwrap();
+    LOCK_STATS
+        .wait_micros
+        .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+    guard
+}
+
+pub fn estimate_size(key: &str, val: &RespValue) -> usize {
+    let val_size = match val {
+        RespValue::SimpleString(s) | RespValue::Error(s) | RespValue::BulkString(Some(s)) => {
+            s.len()
+        }
+        RespValue::Integer(_) => std::mem::size_of::<i64>(),
+        RespValue::Array(arr) => arr.iter().map(|v| estimate_size("", v)).sum(),
+        RespValue::BulkString(None) | RespValue::Null => 0,
+    };
+    key.len() + val_size
+}
+
+// The store is ordered, so taking the first entries would only ever sample
+// the smallest keys. Instead the keyspace is cut into `limit` equal slices and
+// one entry is taken from each, at the same random offset into every slice.
+pub fn sample_entries(limit: usize) -> Vec<(String, usize)> {
+    use std::hash::{BuildHasher, Hasher};
+
+    let map = read_cache();
+    let len = map.len();
+    let seed = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    let phase = seed as usize % len.max(1);
+    let mut positions = (0..limit.min(len))
+        .map(|i| {
+            if len <= limit {
+                i
+            } else {
+                (i * len + phase) / limit
+            }
+        })
+        .peekable();
+
+    map.iter()
+        .enumerate()
+        .filter(|(i, _)| positions.next_if_eq(i).is_some())
+        .map(|(_, (key, val))| (key.clone(), estimate_size(key, val)))
+        .collect()
+}
 
 fn ping(args: Vec<RespValue>) -> RespValue {
     if args.is_empty() {
         RespValue::SimpleString("PONG".to_string())
     } else {
-        match &args[0] {
-            RespValue::BulkString(Some(s)) => RespValue::SimpleString(s.clone()),
-            _ => RespValue::Error("Invalid PING argument".to_string()),
+        match args[0].as_str() {
+            Some(s) => RespValue::SimpleString(s.to_string()),
+            None => RespValue::Error("Invalid PING argument".to_string()),
         }
     }
 }
 
+// Cluster clients send these even to a single node; there is nothing to do.
+fn cluster_noop(_args: Vec<RespValue>) -> RespValue {
+    RespValue::SimpleString("OK".to_string())
+}
+
 fn get(args: Vec<RespValue>) -> RespValue {
-    let key = match args.get(0) {
-        Some(RespValue::BulkString(Some(k))) => k,
-        _ => return RespValue::Error("Missing key for GET".to_string()),
+    let Some(key) = args.first().and_then(RespValue::as_str) else {
+        return RespValue::Error("Missing key for GET".to_string());
     };
 
-    let map = CACHE.read().unwrap();
-    match map.get(key) {
+    let map = read_cache();
+    let val = map.get(key);
+    STATS.record_lookup(val.is_some());
+    match val {
         Some(val) => val.clone(),
         None => RespValue::Null,
     }
 }
 
+fn getrange(args: Vec<RespValue>) -> RespValue {
+    if args.len() != 3 {
+        return RespValue::Error("ERR GETRANGE requires key, start and end".to_string());
+    }
+
+    let Some(key) = args[0].as_str() else {
+        return RespValue::Error("ERR Invalid key for GETRANGE".to_string());
+    };
+
+    let (mut start, mut end) = match (args[1].as_i64(), args[2].as_i64()) {
+        (Some(s), Some(e)) => (s, e),
+        _ => return RespValue::Error("ERR value is not an integer or out of range".to_string()),
+    };
+
+    let map = read_cache();
+    let val = map.get(key);
+    STATS.record_lookup(val.is_some());
+    let bytes = match val.map(RespValue::as_bytes) {
+        Some(Some(bytes)) => bytes,
+        Some(None) => {
+            return RespValue::Error(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+            );
+        }
+        None => return RespValue::BulkString(Some(String::new())),
+    };
+    let len = bytes.len() as i64;
+
+    if start < 0 && end < 0 && start > end {
+        return RespValue::BulkString(Some(String::new()));
+    }
+    if start < 0 {
+        start += len;
+    }
+    if end < 0 {
+        end += len;
+    }
+    start = start.max(0);
+    end = end.max(0).min(len - 1);
+
+    if len == 0 || start > end {
+        return RespValue::BulkString(Some(String::new()));
+    }
+
+    let range = &bytes[start as usize..=end as usize];
+    RespValue::BulkString(Some(String::from_utf8_lossy(range).to_string()))
+}
+
+const DELMATCH_BATCH: usize = 128;
+
+fn delmatch(args: Vec<RespValue>, ctx: &ConnCtx) -> RespValue {
+    let Some(pattern) = (args.len() == 1).then(|| args[0].as_str()).flatten() else {
+        return RespValue::Error("ERR DELMATCH requires a pattern".to_string());
+    };
+
+    let candidates: Vec<String> = {
+        let map = read_cache();
+        matching_keys(&map, pattern).cloned().collect()
+    };
+
+    // Delete in small batches so the write lock is never held for long.
+    ctx.suppress_propagation();
+    let mut deleted = 0;
+    for batch in candidates.chunks(DELMATCH_BATCH) {
+        let mut map = write_cache();
+        for key in batch {
+            if map.remove(key).is_some() {
+                deleted += 1;
+                ctx.propagate(RespValue::Array(vec![
+                    RespValue::BulkString(Some("DEL".to_string())),
+                    RespValue::BulkString(Some(key.clone())),
+                ]));
+            }
+        }
+    }
+
+    RespValue::Integer(deleted)
+}
+
+// The cap set by KEYS' optional COUNT argument.
+pub(crate) fn keys_limit(options: &[RespValue]) -> Result<usize, RespValue> {
+    match options {
+        [] => Ok(usize::MAX),
+        [opt, n]
+            if opt
+                .as_str()
+                .is_some_and(|o| o.eq_ignore_ascii_case("count")) =>
+        {
+            n.as_i64()
+                .and_then(|n| usize::try_from(n).ok())
+                .ok_or_else(|| {
+                    RespValue::Error("ERR value is not an integer or out of range".to_string())
+                })
+        }
+        _ => Err(RespValue::Error("ERR syntax error".to_string())),
+    }
+}
+
+fn keys(args: Vec<RespValue>) -> RespValue {
+    let Some(pattern) = args.first().and_then(RespValue::as_str) else {
+        return RespValue::Error("ERR Missing pattern for KEYS".to_string());
+    };
+
+    let limit = match keys_limit(&args[1..]) {
+        Ok(limit) => limit,
+        Err(e) => return e,
+    };
+
+    let map = read_cache();
+    let matched = matching_keys(&map, pattern)
+        .take(limit)
+        .map(|key| RespValue::BulkString(Some(key.clone())))
+        .collect();
+
+    RespValue::Array(matched)
+}
+
 fn set(args: Vec<RespValue>) -> RespValue {
     if args.len() < 2 {
         return RespValue::Error("SET requires key and value".to_string());
     }
+    // No SET options are supported; ignoring one such as EX would silently
+    // drop what the client asked for.
+    if args.len() > 2 {
+        return RespValue::Error("ERR syntax error".to_string());
+    }
 
-    let key = match &args[0] {
-        RespValue::BulkString(Some(k)) => k.clone(),
-        _ => return RespValue::Error("Invalid key for SET".to_string()),
+    let mut args = args.into_iter();
+    let Some(key) = args.next().and_then(RespValue::into_string) else {
+        return RespValue::Error("Invalid key for SET".to_string());
     };
 
-    let val = args[1].clone();
-    let mut map = CACHE.write().unwrap();
+    // Take the value out of the argument list rather than cloning it, so a
+    // large payload is not duplicated on its way into the store.
+    let val = args.next().unwrap();
+    let mut map = write_cache();
     map.insert(key, val);
 
     RespValue::SimpleString("OK".to_string())
 }
 
-fn del(args: Vec<RespValue>) -> RespValue {
-    let mut deleted = 0;
-    let mut map = CACHE.write().unwrap();
+// All pairs are applied under one write lock and the command is journaled as
+// a single record, so replay of a torn tail can never apply only some pairs.
+fn mset(args: Vec<RespValue>) -> RespValue {
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return RespValue::Error("ERR wrong number of arguments for 'mset' command".to_string());
+    }
+
+    let mut pairs = Vec::with_capacity(args.len() / 2);
+    let mut args = args.into_iter();
+    while let (Some(key), Some(val)) = (args.next(), args.next()) {
+        let Some(key) = key.into_string() else {
+            return RespValue::Error("ERR Invalid key for MSET".to_string());
+        };
+        pairs.push((key, val));
+    }
+
+    let mut map = write_cache();
+    map.extend(pairs);
+
+    RespValue::SimpleString("OK".to_string())
+}
 
+fn cset(args: Vec<RespValue>, ctx: &ConnCtx) -> RespValue {
+    let mut args = args.into_iter().map(RespValue::into_string);
+    let (Some(Some(key)), Some(Some(expected)), Some(Some(new))) =
+        (args.next(), args.next(), args.next())
+    else {
+        return RespValue::Error("ERR CSET requires key, expected and new value".to_string());
+    };
+
+    let mut map = write_cache();
+    let matches = map.get(&key).and_then(RespValue::as_str) == Some(expected.as_str());
+
+    // Only a successful swap is journaled, and as the SET it amounts to.
+    ctx.suppress_propagation();
+    if !matches {
+        return RespValue::Integer(0);
+    }
+
+    ctx.propagate(RespValue::Array(vec![
+        RespValue::BulkString(Some("SET".to_string())),
+        RespValue::BulkString(Some(key.clone())),
+        RespValue::BulkString(Some(new.clone())),
+    ]));
+    map.insert(key, RespValue::BulkString(Some(new)));
+    RespValue::Integer(1)
+}
+
+fn del(args: Vec<RespValue>) -> RespValue {
+    let mut keys = Vec::with_capacity(args.len());
     for arg in args {
-        if let RespValue::BulkString(Some(k)) = arg {
-            if map.remove(&k).is_some() {
-                deleted += 1;
-            }
+        match arg.into_string() {
+            Some(k) => keys.push(k),
+            None => return RespValue::Error("ERR DEL keys must be bulk strings".to_string()),
+        }
+    }
+
+    let mut deleted: i64 = 0;
+    let mut map = write_cache();
+    for key in keys {
+        if map.remove(&key).is_some() {
+            deleted += 1;
         }
     }
 
     RespValue::Integer(deleted)
 }
 
-pub fn handle_resp(command: &RespValue) -> RespValue {
-    let arr = match command {
-        RespValue::Array(a) => a,
-        _ => return RespValue::Error("Only arrays accepted.".to_string()),
+fn unknown_command(name: &str, args: &[RespValue]) -> RespValue {
+    let mut args_desc = String::new();
+    for arg in args {
+        if args_desc.len() >= 128 {
+            break;
+        }
+        if let Some(a) = arg.as_str() {
+            let remaining = 128 - args_desc.len();
+            let truncated: String = a.chars().take(remaining).collect();
+            args_desc.push_str(&format!("'{}' ", truncated));
+        }
+    }
+
+    let name: String = name.chars().take(128).collect();
+    let msg = format!(
+        "ERR unknown command '{}', with args beginning with: {}",
+        name, args_desc
+    );
+    // The text comes from the client; a raw CRLF would end the error line early.
+    RespValue::Error(msg.replace(['\r', '\n'], " "))
+}
+
+pub fn is_write_command(command: &RespValue) -> bool {
+    command
+        .as_array()
+        .and_then(|arr| arr.first())
+        .and_then(RespValue::as_str)
+        .and_then(lookup_command)
+        .is_some_and(|spec| spec.has_flag("write"))
+}
+
+pub fn handle_resp(command: &RespValue, ctx: &ConnCtx) -> RespValue {
+    let Some(arr) = command.as_array() else {
+        return RespValue::Error("Only arrays accepted.".to_string());
     };
 
-    let cmd = match arr.get(0) {
-        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
-        _ => return RespValue::Error("Bulk string command expected".to_string()),
+    let Some(name) = arr.first().and_then(RespValue::as_str) else {
+        return RespValue::Error("Bulk string command expected".to_string());
     };
+    let cmd = name.to_lowercase();
 
     let args = arr[1..].to_vec();
 
-    match cmd.as_str() {
+    let index = command_index(&cmd);
+    let spec = index.map(|i| &COMMANDS[i]);
+    if let (Some(index), Some(spec)) = (index, spec) {
+        if !spec.accepts_argc(arr.len()) {
+            return RespValue::Error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                spec.name
+            ));
+        }
+        STATS.record_command(index);
+    }
+
+    let is_write = spec.is_some_and(|spec| spec.has_flag("write"));
+    if cmd != "client" {
+        wait_while_paused(is_write);
+    }
+
+    let response = match cmd.as_str() {
         "ping" => ping(args),
         "get" => get(args),
+        "getrange" => getrange(args),
+        "keys" => keys(args),
         "set" => set(args),
+        "mset" => mset(args),
+        "cset" => cset(args, ctx),
         "del" => del(args),
-        _ => RespValue::Error("Invalid command".to_string()),
+        "delmatch" => delmatch(args, ctx),
+        "debug" => debug(args),
+        "command" => commands(args),
+        "object" => object(args),
+        "memory" => memory(args),
+        "config" => config(args),
+        "info" => info(args),
+        "client" => client(args),
+        "hello" => hello(args, ctx),
+        "capabilities" => capabilities(args),
+        "readonly" | "readwrite" | "asking" => cluster_noop(args),
+        _ => unknown_command(name, &args),
+    };
+
+    if is_write && !matches!(response, RespValue::Error(_)) {
+        DIRTY.fetch_add(1, Ordering::Relaxed);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn contended_write_is_counted_as_a_wait() {
+        let _state = shared();
+        let before = LOCK_STATS.write_waits.load(Ordering::Relaxed);
+
+        let guard = write_cache();
+        let writer = thread::spawn(|| {
+            write_cache().insert("lockstats:contended".to_string(), bulk("1"));
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop(guard);
+        writer.join().unwrap();
+
+        assert!(LOCK_STATS.write_waits.load(Ordering::Relaxed) > before);
+        assert_eq!(read_cache().get("lockstats:contended"), Some(&bulk("1")));
+    }
+
+    #[test]
+    fn unknown_command_echoes_name_and_args() {
+        let _state = shared();
+        let ctx = ConnCtx::new("test".to_string());
+        let reply = handle_resp(&command(&["FOO", "bar", "baz"]), &ctx);
+        assert_eq!(
+            reply,
+            RespValue::Error(
+                "ERR unknown command 'FOO', with args beginning with: 'bar' 'baz' ".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_command_error_stays_on_one_line() {
+        let _state = shared();
+        let ctx = ConnCtx::new("test".to_string());
+        let reply = handle_resp(&command(&["foo\r\n+OK!", "a\nb"]), &ctx);
+        let wire = marshal(&reply);
+        assert!(wire.starts_with(b"-ERR unknown command 'foo  +OK!'"));
+        assert_eq!(wire.iter().filter(|&&b| b == b'\n').count(), 1);
+        assert!(wire.ends_with(b"'a b' \r\n"));
+    }
+
+    #[test]
+    fn dispatch_enforces_command_arity() {
+        let _state = shared();
+        let ctx = ConnCtx::new("test".to_string());
+        let wrong_args = |name: &str| {
+            RespValue::Error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                name
+            ))
+        };
+
+        assert_eq!(handle_resp(&command(&["DEL"]), &ctx), wrong_args("del"));
+        assert_eq!(
+            handle_resp(&command(&["get", "a", "b"]), &ctx),
+            wrong_args("get")
+        );
+        assert_eq!(
+            handle_resp(&command(&["SET", "k"]), &ctx),
+            wrong_args("set")
+        );
+    }
+
+    #[test]
+    fn set_rejects_options_it_does_not_support() {
+        let _state = shared();
+        let ctx = ConnCtx::new("test".to_string());
+        assert_eq!(
+            handle_resp(&command(&["SET", "setopt:ex", "v", "EX", "1"]), &ctx),
+            RespValue::Error("ERR syntax error".to_string())
+        );
+        assert_eq!(
+            handle_resp(&command(&["SET", "setopt:keepttl", "v", "KEEPTTL"]), &ctx),
+            RespValue::Error("ERR syntax error".to_string())
+        );
+        assert_eq!(read_cache().get("setopt:ex"), None);
+    }
+
+    #[test]
+    fn getrange_matches_redis_edge_cases() {
+        let _state = shared();
+        let ctx = ConnCtx::new("test".to_string());
+        write_cache().insert("getrange:k".to_string(), bulk("Hello World"));
+        write_cache().insert("getrange:empty".to_string(), bulk(""));
+        let range = |key: &str, start: &str, end: &str| {
+            handle_resp(&command(&["GETRANGE", key, start, end]), &ctx)
+        };
+
+        let cases = [
+            ("0", "4", "Hello"),
+            ("0", "-1", "Hello World"),
+            ("-5", "-1", "World"),
+            ("-100", "-1", "Hello World"),
+            ("-100", "2", "Hel"),
+            ("6", "1000", "World"),
+            ("5", "3", ""),
+            ("-1", "-5", ""),
+            ("100", "200", ""),
+            ("10", "10", "d"),
+        ];
+        for (start, end, expected) in cases {
+            assert_eq!(
+                range("getrange:k", start, end),
+                bulk(expected),
+                "{} {}",
+                start,
+                end
+            );
+        }
+
+        assert_eq!(range("getrange:empty", "0", "-1"), bulk(""));
+        assert_eq!(range("getrange:missing", "0", "-1"), bulk(""));
+        assert_eq!(
+            range("getrange:k", "a", "1"),
+            RespValue::Error("ERR value is not an integer or out of range".to_string())
+        );
+    }
+
+    #[test]
+    fn cluster_commands_are_accepted_as_no_ops() {
+        let _state = shared();
+        let ctx = ConnCtx::new("test".to_string());
+        for name in ["READONLY", "READWRITE", "ASKING"] {
+            assert_eq!(
+                handle_resp(&command(&[name]), &ctx),
+                RespValue::SimpleString("OK".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn keys_count_caps_the_number_of_matches() {
+        let _state = shared();
+        let ctx = ConnCtx::new("test".to_string());
+        for i in 0..10 {
+            write_cache().insert(format!("keyscap:{}", i), bulk("v"));
+        }
+        let count = |args: &[&str]| match handle_resp(&command(args), &ctx) {
+            RespValue::Array(keys) => keys.len(),
+            other => panic!("unexpected reply {:?}", other),
+        };
+
+        assert_eq!(count(&["KEYS", "keyscap:*"]), 10);
+        assert_eq!(count(&["KEYS", "keyscap:*", "COUNT", "3"]), 3);
+        assert_eq!(count(&["KEYS", "keyscap:*", "count", "50"]), 10);
+        assert_eq!(count(&["KEYS", "keyscap:*", "COUNT", "0"]), 0);
+        assert_eq!(
+            handle_resp(&command(&["KEYS", "keyscap:*", "COUNT", "-1"]), &ctx),
+            RespValue::Error("ERR value is not an integer or out of range".to_string())
+        );
+    }
+
+    #[test]
+    fn delmatch_removes_only_matching_keys_and_journals_dels() {
+        let _state = shared();
+        for key in [
+            "delmatch:session:1",
+            "delmatch:session:2",
+            "delmatch:user:1",
+        ] {
+            write_cache().insert(key.to_string(), bulk("v"));
+        }
+        let ctx = ConnCtx::new("test".to_string());
+
+        let reply = handle_resp(&command(&["DELMATCH", "delmatch:session:*"]), &ctx);
+        assert_eq!(reply, RespValue::Integer(2));
+        assert_eq!(
+            ctx.take_propagation(),
+            Some(vec![
+                command(&["DEL", "delmatch:session:1"]),
+                command(&["DEL", "delmatch:session:2"]),
+            ])
+        );
+        assert_eq!(
+            keys_matching("delmatch:*", usize::MAX),
+            vec!["delmatch:user:1"]
+        );
+
+        let reply = handle_resp(&command(&["DELMATCH", "delmatch:session:*"]), &ctx);
+        assert_eq!(reply, RespValue::Integer(0));
+        assert_eq!(ctx.take_propagation(), Some(vec![]));
+    }
+
+    #[test]
+    fn del_counts_a_large_batch() {
+        let _state = shared();
+        let keys: Vec<String> = (0..5000).map(|i| format!("delbatch:{}", i)).collect();
+        for key in &keys {
+            write_cache().insert(key.clone(), bulk("v"));
+        }
+
+        let mut args: Vec<&str> = vec!["DEL"];
+        args.extend(keys.iter().map(String::as_str));
+        args.push("delbatch:missing");
+        let reply = handle_resp(&command(&args), &ConnCtx::new("test".to_string()));
+
+        assert_eq!(reply, RespValue::Integer(5000));
+        assert!(keys_matching("delbatch:*", usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn del_rejects_a_malformed_key() {
+        let _state = shared();
+        write_cache().insert("delbad:k".to_string(), bulk("v"));
+        let del = RespValue::Array(vec![bulk("DEL"), bulk("delbad:k"), RespValue::Integer(7)]);
+
+        assert_eq!(
+            handle_resp(&del, &ConnCtx::new("test".to_string())),
+            RespValue::Error("ERR DEL keys must be bulk strings".to_string())
+        );
+        assert_eq!(read_cache().get("delbad:k"), Some(&bulk("v")));
+    }
+
+    #[test]
+    fn large_set_value_is_not_buffered_more_than_twice() {
+        let _state = shared();
+        const LEN: usize = 8 * 1024 * 1024;
+        let wire = marshal(&command(&["SET", "bigset:key", &"x".repeat(LEN)]));
+        let ctx = ConnCtx::internal();
+
+        let peak = peak_bytes(|| {
+            let command = read_resp(&mut std::io::Cursor::new(&wire)).unwrap();
+            assert_eq!(
+                handle_resp(&command, &ctx),
+                RespValue::SimpleString("OK".into())
+            );
+        });
+        // The read buffer becomes the parsed value, and the only copy is the
+        // one the store keeps while the original stays around for the AOF.
+        assert!(peak < (2 * LEN + LEN / 4) as u64, "peak {} bytes", peak);
+
+        let mut decoder = Decoder::new();
+        let peak = peak_bytes(|| {
+            for chunk in wire.chunks(64 * 1024) {
+                for command in decoder.feed(chunk) {
+                    handle_resp(&command, &ctx);
+                }
+            }
+        });
+        assert!(peak < (2 * LEN + LEN / 4) as u64, "peak {} bytes", peak);
+
+        let RespValue::BulkString(Some(stored)) = run(&["GET", "bigset:key"]) else {
+            panic!("bigset:key was not stored");
+        };
+        assert_eq!(stored.len(), LEN);
+        run(&["DEL", "bigset:key"]);
+    }
+
+    #[test]
+    fn cset_swaps_only_on_a_match() {
+        let _state = shared();
+        run(&["SET", "cset:key", "old"]);
+
+        let ctx = ConnCtx::new("test".to_string());
+        let mismatch = handle_resp(&command(&["CSET", "cset:key", "other", "new"]), &ctx);
+        assert_eq!(mismatch, RespValue::Integer(0));
+        assert_eq!(ctx.take_propagation(), Some(vec![]));
+        assert_eq!(run(&["GET", "cset:key"]), bulk("old"));
+
+        let swapped = handle_resp(&command(&["CSET", "cset:key", "old", "new"]), &ctx);
+        assert_eq!(swapped, RespValue::Integer(1));
+        assert_eq!(
+            ctx.take_propagation(),
+            Some(vec![command(&["SET", "cset:key", "new"])])
+        );
+        assert_eq!(run(&["GET", "cset:key"]), bulk("new"));
+
+        let missing = handle_resp(&command(&["CSET", "cset:missing", "old", "new"]), &ctx);
+        assert_eq!(missing, RespValue::Integer(0));
+        assert_eq!(run(&["GET", "cset:missing"]), RespValue::Null);
+        run(&["DEL", "cset:key"]);
+    }
+
+    #[test]
+    fn samples_are_spread_across_the_keyspace() {
+        let _state = exclusive();
+        let saved = std::mem::take(&mut *write_cache());
+        for i in 0..1000 {
+            write_cache().insert(format!("spread:{:04}", i), bulk("v"));
+        }
+        let samples = sample_entries(100);
+        let few = sample_entries(2000);
+        *write_cache() = saved;
+
+        // One sample from each run of ten consecutive keys.
+        assert_eq!(samples.len(), 100);
+        for (slice, (key, _)) in samples.iter().enumerate() {
+            let index: usize = key["spread:".len()..].parse().unwrap();
+            assert_eq!(index / 10, slice, "{}", key);
+        }
+        assert_eq!(few.len(), 1000);
+    }
+
+    fn keyspace_for_scans(keys: usize) -> Store {
+        let mut map = Store::new();
+        for i in 0..keys {
+            for prefix in ["key", "user:", "users", "us"] {
+                map.insert(format!("{}{}", prefix, i), bulk("v"));
+            }
+        }
+        map.insert("user:*".to_string(), bulk("v"));
+        map.insert("user:[1]".to_string(), bulk("v"));
+        map
+    }
+
+    fn full_scan<'a>(map: &'a Store, pattern: &'a str) -> Vec<&'a String> {
+        map.keys().filter(|key| glob_match(pattern, key)).collect()
+    }
+
+    #[test]
+    fn prefix_scan_returns_the_same_keys_as_a_full_scan() {
+        let map = keyspace_for_scans(300);
+        for pattern in [
+            "user:*",
+            "user:1?",
+            "user:[12]*",
+            "us*",
+            "users2*",
+            "user:\\*",
+            "user:\\[1]",
+            "*:1*",
+            "key29",
+            "nosuch*",
+            "",
+            "*",
+        ] {
+            let scanned: Vec<_> = matching_keys(&map, pattern).collect();
+            assert_eq!(scanned, full_scan(&map, pattern), "{}", pattern);
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark; run with `cargo test --release -- --ignored --nocapture`"]
+    fn bench_prefix_scan_against_full_scan() {
+        let map = keyspace_for_scans(50_000);
+        let pattern = "key1999*";
+
+        let start = Instant::now();
+        let scanned = matching_keys(&map, pattern).count();
+        let prefix = start.elapsed();
+
+        let start = Instant::now();
+        let full = full_scan(&map, pattern).len();
+        let unanchored = start.elapsed();
+
+        assert_eq!(scanned, full);
+        println!(
+            "KEYS {} over {} keys: prefix scan {:?}, full scan {:?}",
+            pattern,
+            map.len(),
+            prefix,
+            unanchored
+        );
     }
 }
@@ -0,0 +1,256 @@
+use crate::prelude::*;
+
+const SIZE_BUCKETS: [(&str, usize); 3] = [("<64B", 64), ("<1KB", 1024), ("<64KB", 64 * 1024)];
+
+fn size_histogram() -> RespValue {
+    let mut counts = [0; SIZE_BUCKETS.len() + 1];
+
+    let map = read_cache();
+    for (key, val) in map.iter() {
+        let size = estimate_size(key, val);
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .position(|(_, limit)| size < *limit)
+            .unwrap_or(SIZE_BUCKETS.len());
+        counts[bucket] += 1;
+    }
+
+    let labels = SIZE_BUCKETS
+        .iter()
+        .map(|(label, _)| *label)
+        .chain([">=64KB"]);
+    let mut reply = Vec::with_capacity(counts.len() * 2);
+    for (label, count) in labels.zip(counts) {
+        reply.push(RespValue::BulkString(Some(label.to_string())));
+        reply.push(RespValue::Integer(count));
+    }
+
+    RespValue::Array(reply)
+}
+
+fn lock_stats() -> RespValue {
+    let fields = [
+        ("reads", &LOCK_STATS.reads),
+        ("read_waits", &LOCK_STATS.read_waits),
+        ("writes", &LOCK_STATS.writes),
+        ("write_waits", &LOCK_STATS.write_waits),
+        ("wait_micros", &LOCK_STATS.wait_micros),
+    ];
+
+    let mut reply = Vec::with_capacity(fields.len() * 2);
+    for (label, counter) in fields {
+        reply.push(RespValue::BulkString(Some(label.to_string())));
+        reply.push(RespValue::Integer(counter.load(Ordering::Relaxed) as i64));
+    }
+
+    RespValue::Array(reply)
+}
+
+// Canned replies for client parser tests. Only RESP2 is spoken, so RESP3 types
+// are sent in the form Redis falls back to for RESP2 clients.
+fn protocol(args: &[RespValue]) -> RespValue {
+    let kind = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
+        _ => return RespValue::Error("ERR DEBUG PROTOCOL requires a type".to_string()),
+    };
+    let bulk = |s: &str| RespValue::BulkString(Some(s.to_string()));
+    let numbers = || (0..3).map(RespValue::Integer).collect();
+
+    match kind.as_str() {
+        "string" => bulk("Hello World"),
+        "integer" => RespValue::Integer(12345),
+        "double" => bulk("3.141"),
+        "bignum" => bulk("1234567999999999999999999999999999999"),
+        "null" => RespValue::Null,
+        "array" | "set" | "push" => RespValue::Array(numbers()),
+        "map" => RespValue::Array(
+            (0..3)
+                .flat_map(|i| [RespValue::Integer(i), RespValue::Integer((i == 1) as i64)])
+                .collect(),
+        ),
+        "true" => RespValue::Integer(1),
+        "false" => RespValue::Integer(0),
+        "verbatim" => bulk("This is a verbatim\nstring"),
+        "err" => RespValue::Error("ERR This is an error".to_string()),
+        _ => RespValue::Error(
+            "ERR Wrong protocol type name. Please use one of the following: string|integer|double|bignum|null|array|set|map|true|false|verbatim|err|push"
+                .to_string(),
+        ),
+    }
+}
+
+fn flush_aof() -> RespValue {
+    match sync_active_aof() {
+        Ok(()) => RespValue::SimpleString("OK".to_string()),
+        Err(e) => RespValue::Error(format!("ERR AOF sync failed: {}", e)),
+    }
+}
+
+fn pause_writes(args: &[RespValue]) -> RespValue {
+    let millis = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => match s.parse::<u64>() {
+            Ok(ms) => ms,
+            Err(_) => {
+                return RespValue::Error(
+                    "ERR timeout is not an integer or out of range".to_string(),
+                );
+            }
+        },
+        _ => return RespValue::Error("ERR DEBUG PAUSE-WRITES requires a timeout".to_string()),
+    };
+
+    pause_clients(Duration::from_millis(millis), true);
+    RespValue::SimpleString("OK".to_string())
+}
+
+fn aof_sync_fail(args: &[RespValue]) -> RespValue {
+    let enabled = match args.first().and_then(RespValue::as_str) {
+        Some(s) if s.eq_ignore_ascii_case("on") => true,
+        Some(s) if s.eq_ignore_ascii_case("off") => false,
+        _ => return RespValue::Error("ERR DEBUG AOF-SYNC-FAIL requires on or off".to_string()),
+    };
+
+    SYNC_FAULT.store(enabled, Ordering::Relaxed);
+    RespValue::SimpleString("OK".to_string())
+}
+
+// Subcommands that change server behaviour purely for tests.
+const TEST_HOOKS: &[&str] = &["pause-writes", "aof-sync-fail"];
+
+pub fn debug(args: Vec<RespValue>) -> RespValue {
+    let sub = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
+        _ => return RespValue::Error("ERR DEBUG requires a subcommand".to_string()),
+    };
+
+    if TEST_HOOKS.contains(&sub.as_str()) && !ENABLE_DEBUG.load(Ordering::Relaxed) {
+        return RespValue::Error(format!(
+            "ERR DEBUG {} is disabled, restart the server with --enable-debug",
+            sub.to_uppercase()
+        ));
+    }
+
+    match sub.as_str() {
+        "size-histogram" => size_histogram(),
+        "lockstats" => lock_stats(),
+        "protocol" => protocol(&args[1..]),
+        "flushaof" => flush_aof(),
+        "pause-writes" => pause_writes(&args[1..]),
+        "aof-sync-fail" => aof_sync_fail(&args[1..]),
+        _ => RespValue::Error("ERR Invalid DEBUG subcommand".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    fn histogram_counts() -> Vec<i64> {
+        let RespValue::Array(reply) = debug(vec![bulk("size-histogram")]) else {
+            panic!("SIZE-HISTOGRAM should reply with an array");
+        };
+        reply
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|count| match count {
+                RespValue::Integer(n) => *n,
+                other => panic!("expected an integer count, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn size_histogram_buckets_keys_by_value_size() {
+        let _state = exclusive();
+        let before = histogram_counts();
+
+        let sizes = [
+            ("tiny", 10),
+            ("small", 500),
+            ("medium", 10_000),
+            ("large", 100_000),
+        ];
+        let mut map = write_cache();
+        for (name, size) in sizes {
+            map.insert(format!("hist:{}", name), bulk(&"x".repeat(size)));
+        }
+        drop(map);
+
+        let after = histogram_counts();
+        let added: Vec<i64> = after.iter().zip(&before).map(|(a, b)| a - b).collect();
+        assert_eq!(added, vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn pause_writes_keeps_a_snapshot_consistent() {
+        let _state = exclusive();
+        run(&["SET", "snapshot:k", "before"]);
+
+        assert!(matches!(
+            run(&["DEBUG", "PAUSE-WRITES", "200"]),
+            RespValue::Error(e) if e.contains("--enable-debug")
+        ));
+
+        ENABLE_DEBUG.store(true, Ordering::Relaxed);
+        run(&["DEBUG", "PAUSE-WRITES", "300"]);
+        let writer = thread::spawn(|| run(&["SET", "snapshot:k", "after"]));
+
+        let first = read_cache().get("snapshot:k").cloned();
+        thread::sleep(Duration::from_millis(100));
+        let second = read_cache().get("snapshot:k").cloned();
+        let read_during_pause = run(&["GET", "snapshot:k"]);
+
+        writer.join().unwrap();
+        ENABLE_DEBUG.store(false, Ordering::Relaxed);
+
+        assert_eq!(first, Some(bulk("before")));
+        assert_eq!(second, first);
+        assert_eq!(read_during_pause, bulk("before"));
+        assert_eq!(run(&["GET", "snapshot:k"]), bulk("after"));
+    }
+
+    #[test]
+    fn protocol_map_is_sent_as_a_flat_key_value_array() {
+        let _state = shared();
+        let map = run(&["DEBUG", "PROTOCOL", "map"]);
+        assert_eq!(
+            marshal(&map),
+            b"*6\r\n:0\r\n:0\r\n:1\r\n:1\r\n:2\r\n:0\r\n".to_vec()
+        );
+
+        assert_eq!(marshal(&run(&["DEBUG", "PROTOCOL", "true"])), b":1\r\n");
+        assert_eq!(marshal(&run(&["DEBUG", "PROTOCOL", "null"])), b"$-1\r\n");
+        let unknown = run(&["DEBUG", "PROTOCOL", "attrib"]);
+        assert!(matches!(unknown, RespValue::Error(e) if e.starts_with("ERR Wrong protocol type")));
+    }
+
+    #[test]
+    fn aof_sync_fail_makes_syncs_error_until_turned_off() {
+        let _state = exclusive();
+        let disabled = run(&["DEBUG", "AOF-SYNC-FAIL", "on"]);
+        assert!(matches!(disabled, RespValue::Error(e) if e.contains("--enable-debug")));
+
+        let dir = temp_dir("debug-sync-fail");
+        let aof = Mutex::new(Aof::new(dir.to_str().unwrap(), "aof.log", 1).unwrap());
+        let set = command(&["SET", "syncfail:k", "v"]);
+        aof.lock().unwrap().write(&set).unwrap();
+
+        ENABLE_DEBUG.store(true, Ordering::Relaxed);
+        let on = run(&["DEBUG", "AOF-SYNC-FAIL", "on"]);
+        let failed = sync_aof(&aof);
+        let ping = run(&["PING"]);
+        let off = run(&["DEBUG", "AOF-SYNC-FAIL", "off"]);
+        ENABLE_DEBUG.store(false, Ordering::Relaxed);
+
+        assert_eq!(on, RespValue::SimpleString("OK".to_string()));
+        assert_eq!(failed.unwrap_err().to_string(), "injected sync failure");
+        assert_eq!(ping, RespValue::SimpleString("PONG".to_string()));
+        assert_eq!(off, RespValue::SimpleString("OK".to_string()));
+
+        // Nothing written before the failure is lost once syncs work again.
+        sync_aof(&aof).unwrap();
+        assert_eq!(std::fs::read(dir.join("aof.log")).unwrap(), marshal(&set));
+    }
+}
@@ -0,0 +1,82 @@
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    // pattern starts just after '['; returns (matched, bytes consumed incl. ']')
+    let mut i = 0;
+    let negate = pattern.first() == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    while i < pattern.len() && pattern[i] != b']' {
+        if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            matched |= pattern[i + 1] == c;
+            i += 2;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let (lo, hi) = if pattern[i] <= pattern[i + 2] {
+                (pattern[i], pattern[i + 2])
+            } else {
+                (pattern[i + 2], pattern[i])
+            };
+            matched |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+
+    if i >= pattern.len() {
+        return None;
+    }
+    Some((matched != negate, i + 1))
+}
+
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    glob_match_bytes(pattern.as_bytes(), s.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while i < s.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, i));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => match match_class(&pattern[p + 1..], s[i]) {
+                Some((true, consumed)) => Some(consumed + 1),
+                Some((false, _)) => None,
+                None => (pattern[p] == s[i]).then_some(1),
+            },
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == s[i]).then_some(2),
+            Some(&c) => (c == s[i]).then_some(1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(step), _) => {
+                p += step;
+                i += 1;
+            }
+            (None, Some((star_p, star_i))) => {
+                backtrack = Some((star_p, star_i + 1));
+                p = star_p + 1;
+                i = star_i + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// The part of a pattern before its first special character. Every key the
+// pattern can match starts with it.
+pub fn literal_prefix(pattern: &str) -> &str {
+    let end = pattern.find(['*', '?', '[', '\\']).unwrap_or(pattern.len());
+    &pattern[..end]
+}
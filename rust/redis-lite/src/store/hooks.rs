@@ -0,0 +1,150 @@
+use crate::prelude::*;
+
+pub type Hook = Box<dyn Fn(&RespValue, &ConnCtx) -> Option<RespValue> + Send + Sync>;
+
+static HOOKS: Lazy<RwLock<Vec<Hook>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+pub fn register_hook(hook: Hook) {
+    HOOKS.write().unwrap().push(hook);
+}
+
+// Hooks run in registration order; the first one to return a reply
+// short-circuits the command before it reaches handle_resp.
+pub fn run_hooks(command: &RespValue, ctx: &ConnCtx) -> Option<RespValue> {
+    first_reply(&HOOKS.read().unwrap(), command, ctx)
+}
+
+fn first_reply(hooks: &[Hook], command: &RespValue, ctx: &ConnCtx) -> Option<RespValue> {
+    hooks.iter().find_map(|hook| hook(command, ctx))
+}
+
+fn read_only_hook(command: &RespValue, _ctx: &ConnCtx) -> Option<RespValue> {
+    (READ_ONLY.load(Ordering::Relaxed) && is_write_command(command))
+        .then(|| RespValue::Error("ERR server is read-only".to_string()))
+}
+
+// Key positions come from the command table, so every write command is
+// covered without each handler checking its own arguments.
+fn key_length_hook(command: &RespValue, _ctx: &ConnCtx) -> Option<RespValue> {
+    (is_write_command(command)
+        && command_keys(command)
+            .iter()
+            .any(|key| exceeds_max_key_bytes(key.len())))
+    .then(|| RespValue::Error("ERR key is too long".to_string()))
+}
+
+// Checked before dispatch rather than in the handlers, so AOF replay, which
+// calls handle_resp directly, still loads values stored before the limit was
+// lowered.
+fn string_size_hook(command: &RespValue, _ctx: &ConnCtx) -> Option<RespValue> {
+    (is_write_command(command)
+        && command_values(command)
+            .iter()
+            .any(|val| exceeds_max_string_bytes(val.len())))
+    .then(|| RespValue::Error("ERR string exceeds maximum allowed size".to_string()))
+}
+
+// While the dataset is replayed in the background only commands flagged
+// "loading" are served; everything else is told to retry later.
+fn loading_hook(command: &RespValue, _ctx: &ConnCtx) -> Option<RespValue> {
+    if !LOADING.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let allowed = match command {
+        RespValue::Array(arr) => match arr.first() {
+            Some(RespValue::BulkString(Some(name))) => {
+                lookup_command(name).is_some_and(|spec| spec.has_flag("loading"))
+            }
+            _ => false,
+        },
+        _ => false,
+    };
+    (!allowed)
+        .then(|| RespValue::Error("LOADING Redis is loading the dataset in memory".to_string()))
+}
+
+// The audit hook goes first so that attempts the other hooks deny are still
+// recorded; it does nothing unless an audit log is open.
+pub fn register_default_hooks() {
+    register_hook(Box::new(audit_hook));
+    register_hook(Box::new(loading_hook));
+    register_hook(Box::new(read_only_hook));
+    register_hook(Box::new(key_length_hook));
+    register_hook(Box::new(string_size_hook));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn read_only_mode_rejects_writes_but_serves_reads() {
+        let _state = exclusive();
+        run(&["SET", "readonly:k", "before"]);
+
+        run(&["CONFIG", "SET", "read-only", "yes"]);
+        let set = run(&["SET", "readonly:k", "after"]);
+        let get = run(&["GET", "readonly:k"]);
+        READ_ONLY.store(false, Ordering::Relaxed);
+
+        assert_eq!(set, RespValue::Error("ERR server is read-only".to_string()));
+        assert_eq!(get, bulk("before"));
+    }
+
+    #[test]
+    fn first_hook_to_reply_short_circuits_the_rest() {
+        static LATER_CALLS: AtomicU64 = AtomicU64::new(0);
+        let hooks: Vec<Hook> = vec![
+            Box::new(|_, _| None),
+            Box::new(|command, _| {
+                let RespValue::Array(arr) = command else {
+                    return None;
+                };
+                (arr.get(1) == Some(&bulk("hooks:blocked")))
+                    .then(|| RespValue::Error("ERR blocked by hook".to_string()))
+            }),
+            Box::new(|_, _| {
+                LATER_CALLS.fetch_add(1, Ordering::Relaxed);
+                None
+            }),
+        ];
+        let ctx = ConnCtx::new("test".to_string());
+
+        assert_eq!(
+            first_reply(&hooks, &command(&["SET", "hooks:blocked", "v"]), &ctx),
+            Some(RespValue::Error("ERR blocked by hook".to_string()))
+        );
+        assert_eq!(
+            first_reply(&hooks, &command(&["GET", "hooks:blocked"]), &ctx),
+            Some(RespValue::Error("ERR blocked by hook".to_string()))
+        );
+        assert_eq!(LATER_CALLS.load(Ordering::Relaxed), 0);
+
+        assert_eq!(
+            first_reply(&hooks, &command(&["SET", "hooks:allowed", "v"]), &ctx),
+            None
+        );
+        assert_eq!(LATER_CALLS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn commands_during_loading_get_the_loading_error() {
+        let _state = exclusive();
+        run(&["SET", "loading:key", "v"]);
+
+        LOADING.store(true, Ordering::Relaxed);
+        let get = run(&["GET", "loading:key"]);
+        let ping = run(&["PING"]);
+        LOADING.store(false, Ordering::Relaxed);
+
+        assert_eq!(
+            get,
+            RespValue::Error("LOADING Redis is loading the dataset in memory".to_string())
+        );
+        assert_eq!(ping, RespValue::SimpleString("PONG".to_string()));
+        assert_eq!(run(&["GET", "loading:key"]), bulk("v"));
+        run(&["DEL", "loading:key"]);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::prelude::*;
+
+pub struct Stats {
+    pub keyspace_hits: AtomicU64,
+    pub keyspace_misses: AtomicU64,
+    pub total_commands_processed: AtomicU64,
+    // One counter per COMMANDS entry, so recording a call takes no lock.
+    command_calls: Vec<AtomicU64>,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Self {
+            keyspace_hits: AtomicU64::new(0),
+            keyspace_misses: AtomicU64::new(0),
+            total_commands_processed: AtomicU64::new(0),
+            command_calls: COMMANDS.iter().map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub fn record_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.keyspace_hits
+        } else {
+            &self.keyspace_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_command(&self, index: usize) {
+        self.total_commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+        self.command_calls[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.keyspace_hits.store(0, Ordering::Relaxed);
+        self.keyspace_misses.store(0, Ordering::Relaxed);
+        self.total_commands_processed.store(0, Ordering::Relaxed);
+        for calls in &self.command_calls {
+            calls.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+pub static STATS: Lazy<Stats> = Lazy::new(Stats::new);
+
+pub static DIRTY: AtomicU64 = AtomicU64::new(0);
+
+pub static LOADING: AtomicBool = AtomicBool::new(false);
+
+pub static START_TIME: OnceCell<Instant> = OnceCell::new();
+
+fn server_section() -> String {
+    server_fields(START_TIME.get().copied(), Instant::now())
+}
+
+fn server_fields(start: Option<Instant>, now: Instant) -> String {
+    let uptime = start.map_or(0, |start| now.saturating_duration_since(start).as_secs());
+    format!(
+        "redis_version:{}\r\nprocess_id:{}\r\nuptime_in_seconds:{}\r\nuptime_in_days:{}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        std::process::id(),
+        uptime,
+        uptime / 86400,
+    )
+}
+
+fn stats_section() -> String {
+    format!(
+        "total_commands_processed:{}\r\nkeyspace_hits:{}\r\nkeyspace_misses:{}\r\n",
+        STATS.total_commands_processed.load(Ordering::Relaxed),
+        STATS.keyspace_hits.load(Ordering::Relaxed),
+        STATS.keyspace_misses.load(Ordering::Relaxed),
+    )
+}
+
+fn persistence_section() -> String {
+    format!(
+        "loading:{}\r\naof_enabled:{}\r\naof_rewrite_in_progress:0\r\naof_last_rewrite_time_sec:-1\r\naof_unjournaled_writes:{}\r\nrdb_changes_since_last_save:{}\r\n",
+        LOADING.load(Ordering::Relaxed) as u8,
+        aof_enabled() as u8,
+        UNJOURNALED_WRITES.load(Ordering::Relaxed),
+        DIRTY.load(Ordering::Relaxed),
+    )
+}
+
+// COMMANDS is kept in name order, so the report comes out sorted.
+fn commandstats_section() -> String {
+    COMMANDS
+        .iter()
+        .zip(&STATS.command_calls)
+        .filter_map(|(spec, calls)| {
+            let calls = calls.load(Ordering::Relaxed);
+            (calls > 0).then(|| format!("cmdstat_{}:calls={}\r\n", spec.name, calls))
+        })
+        .collect()
+}
+
+struct InfoSection {
+    name: &'static str,
+    title: &'static str,
+    render: fn() -> String,
+}
+
+static SECTIONS: &[InfoSection] = &[
+    InfoSection {
+        name: "server",
+        title: "Server",
+        render: server_section,
+    },
+    InfoSection {
+        name: "persistence",
+        title: "Persistence",
+        render: persistence_section,
+    },
+    InfoSection {
+        name: "stats",
+        title: "Stats",
+        render: stats_section,
+    },
+    InfoSection {
+        name: "commandstats",
+        title: "Commandstats",
+        render: commandstats_section,
+    },
+];
+
+pub fn info(args: Vec<RespValue>) -> RespValue {
+    let wanted: Vec<String> = args
+        .iter()
+        .filter_map(|arg| match arg {
+            RespValue::BulkString(Some(s)) => Some(s.to_lowercase()),
+            _ => None,
+        })
+        .collect();
+    let all = wanted.is_empty() || wanted.iter().any(|s| s == "all" || s == "everything");
+
+    let mut report = String::new();
+    for section in SECTIONS {
+        if all || wanted.iter().any(|s| s == section.name) {
+            if !report.is_empty() {
+                report.push_str("\r\n");
+            }
+            report.push_str(&format!("# {}\r\n{}", section.title, (section.render)()));
+        }
+    }
+
+    RespValue::BulkString(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn get_counts_keyspace_hits_and_misses() {
+        let _state = exclusive();
+        run(&["SET", "stats:present", "v"]);
+        let hits = STATS.keyspace_hits.load(Ordering::Relaxed);
+        let misses = STATS.keyspace_misses.load(Ordering::Relaxed);
+
+        run(&["GET", "stats:present"]);
+        assert_eq!(STATS.keyspace_hits.load(Ordering::Relaxed), hits + 1);
+        assert_eq!(STATS.keyspace_misses.load(Ordering::Relaxed), misses);
+
+        run(&["GET", "stats:absent"]);
+        assert_eq!(STATS.keyspace_hits.load(Ordering::Relaxed), hits + 1);
+        assert_eq!(STATS.keyspace_misses.load(Ordering::Relaxed), misses + 1);
+
+        run(&["CONFIG", "RESETSTAT"]);
+        let stats = stats_section();
+        assert!(stats.contains("keyspace_hits:0\r\nkeyspace_misses:0\r\n"));
+    }
+
+    #[test]
+    fn resetstat_zeroes_command_counters() {
+        let _state = exclusive();
+        run(&["PING"]);
+        run(&["PING"]);
+        assert!(commandstats_section().contains("cmdstat_ping:calls="));
+
+        assert_eq!(
+            run(&["CONFIG", "RESETSTAT"]),
+            RespValue::SimpleString("OK".to_string())
+        );
+        assert_eq!(commandstats_section(), "");
+        assert!(stats_section().starts_with("total_commands_processed:0\r\n"));
+
+        run(&["PING"]);
+        assert_eq!(commandstats_section(), "cmdstat_ping:calls=1\r\n");
+    }
+
+    #[test]
+    fn concurrent_commands_are_all_counted() {
+        let _state = exclusive();
+        STATS.reset();
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..1000 {
+                        run(&["PING"]);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(commandstats_section(), "cmdstat_ping:calls=4000\r\n");
+    }
+
+    #[test]
+    fn dirty_counter_tracks_successful_writes() {
+        let _state = exclusive();
+        let before = DIRTY.load(Ordering::Relaxed);
+
+        run(&["SET", "dirty:k", "v"]);
+        run(&["GET", "dirty:k"]);
+        run(&["DEL", "dirty:k"]);
+        run(&["SET", "dirty:k"]);
+        assert_eq!(DIRTY.load(Ordering::Relaxed), before + 2);
+
+        let report = persistence_section();
+        assert!(report.contains(&format!("rdb_changes_since_last_save:{}\r\n", before + 2)));
+        assert!(report.contains("aof_rewrite_in_progress:0\r\n"));
+    }
+
+    fn server_field(section: &str, name: &str) -> u64 {
+        section
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{}:", name)))
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn uptime_counts_up_from_start_time() {
+        let start = Instant::now();
+        let later = |secs| server_fields(Some(start), start + Duration::from_secs(secs));
+
+        assert_eq!(server_field(&later(0), "uptime_in_seconds"), 0);
+        assert_eq!(server_field(&later(1), "uptime_in_seconds"), 1);
+
+        let section = later(2 * 86400 + 5);
+        assert_eq!(server_field(&section, "uptime_in_seconds"), 2 * 86400 + 5);
+        assert_eq!(server_field(&section, "uptime_in_days"), 2);
+    }
+
+    #[test]
+    fn uptime_is_zero_before_start_time_is_set() {
+        let section = server_fields(None, Instant::now());
+        assert_eq!(server_field(&section, "uptime_in_seconds"), 0);
+        assert_eq!(server_field(&section, "uptime_in_days"), 0);
+    }
+}
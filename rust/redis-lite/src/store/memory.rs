@@ -0,0 +1,96 @@
+use crate::prelude::*;
+
+const DOCTOR_SAMPLES: usize = 100;
+const BIG_KEY_BYTES: usize = 64 * 1024;
+
+fn doctor() -> RespValue {
+    let samples = sample_entries(DOCTOR_SAMPLES);
+
+    let Some((largest_key, largest_size)) = samples.iter().max_by_key(|(_, size)| *size) else {
+        return RespValue::BulkString(Some(
+            "Sampled 0 keys; the store is empty, nothing to report.".to_string(),
+        ));
+    };
+
+    let total: usize = samples.iter().map(|(_, size)| size).sum();
+    let mut report = format!(
+        "Sampled {} keys ({} bytes total), largest is '{}' at {} bytes",
+        samples.len(),
+        total,
+        largest_key,
+        largest_size
+    );
+    if *largest_size >= BIG_KEY_BYTES {
+        report.push_str("; consider splitting big keys into smaller ones.");
+    } else {
+        report.push_str("; no big keys found.");
+    }
+
+    RespValue::BulkString(Some(report))
+}
+
+pub fn memory(args: Vec<RespValue>) -> RespValue {
+    let sub = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
+        _ => return RespValue::Error("ERR MEMORY requires a subcommand".to_string()),
+    };
+
+    match sub.as_str() {
+        "doctor" => doctor(),
+        _ => RespValue::Error("ERR Invalid MEMORY subcommand".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn doctor_points_out_a_big_key() {
+        let _state = exclusive();
+        let saved = std::mem::take(&mut *write_cache());
+        for i in 0..10 {
+            write_cache().insert(format!("doctor:small:{}", i), bulk("v"));
+        }
+        write_cache().insert("doctor:big".to_string(), bulk(&"x".repeat(100_000)));
+
+        let report = memory(vec![bulk("DOCTOR")]);
+        *write_cache() = saved;
+
+        let RespValue::BulkString(Some(report)) = report else {
+            panic!("MEMORY DOCTOR should reply with a bulk string");
+        };
+        assert!(report.starts_with("Sampled 11 keys"), "{}", report);
+        assert!(report.contains("largest is 'doctor:big'"), "{}", report);
+        assert!(report.ends_with("consider splitting big keys into smaller ones."));
+    }
+
+    #[test]
+    fn doctor_samples_beyond_the_smallest_keys() {
+        let _state = exclusive();
+        let saved = std::mem::take(&mut *write_cache());
+        for i in 0..150 {
+            write_cache().insert(format!("a{:03}", i), bulk("v"));
+        }
+        write_cache().insert("zz:big".to_string(), bulk(&"x".repeat(200_000)));
+
+        // Each report finds the big key with probability 2/3, so missing it
+        // every time means sampling is stuck at the start of the keyspace.
+        let reports: Vec<_> = (0..30).map(|_| memory(vec![bulk("DOCTOR")])).collect();
+        *write_cache() = saved;
+
+        assert!(
+            reports
+                .iter()
+                .all(|r| r.as_str().unwrap().starts_with("Sampled 100 keys"))
+        );
+        assert!(
+            reports
+                .iter()
+                .any(|r| r.as_str().unwrap().contains("largest is 'zz:big'")),
+            "{:?}",
+            reports[0]
+        );
+    }
+}
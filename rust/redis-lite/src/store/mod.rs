@@ -1,5 +1,23 @@
-use crate::prelude::*;
-
+pub mod capabilities;
+pub mod client;
+pub mod command;
+pub mod config;
 pub mod db;
+pub mod debug;
+pub mod glob;
+pub mod hooks;
+pub mod info;
+pub mod memory;
+pub mod object;
 
+pub use capabilities::*;
+pub use client::*;
+pub use command::*;
+pub use config::*;
 pub use db::*;
+pub use debug::*;
+pub use glob::*;
+pub use hooks::*;
+pub use info::*;
+pub use memory::*;
+pub use object::*;
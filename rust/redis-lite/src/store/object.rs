@@ -0,0 +1,65 @@
+use crate::prelude::*;
+
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+pub fn string_encoding(s: &str) -> &'static str {
+    // Like Redis, only the canonical spelling counts: "007", "+1" and "-0"
+    // parse as integers but would not round-trip, so they stay strings.
+    if s.len() <= 20 && s.parse::<i64>().is_ok_and(|i| i.to_string() == s) {
+        "int"
+    } else if s.len() <= EMBSTR_SIZE_LIMIT {
+        "embstr"
+    } else {
+        "raw"
+    }
+}
+
+fn encoding(args: &[RespValue]) -> RespValue {
+    let key = match args.first() {
+        Some(RespValue::BulkString(Some(k))) => k,
+        _ => return RespValue::Error("ERR Missing key for OBJECT ENCODING".to_string()),
+    };
+
+    let map = read_cache();
+    match map.get(key) {
+        Some(RespValue::BulkString(Some(s))) => {
+            RespValue::BulkString(Some(string_encoding(s).to_string()))
+        }
+        Some(_) => RespValue::BulkString(Some("raw".to_string())),
+        None => RespValue::Null,
+    }
+}
+
+pub fn object(args: Vec<RespValue>) -> RespValue {
+    let sub = match args.first() {
+        Some(RespValue::BulkString(Some(s))) => s.to_lowercase(),
+        _ => return RespValue::Error("ERR OBJECT requires a subcommand".to_string()),
+    };
+
+    match sub.as_str() {
+        "encoding" => encoding(&args[1..]),
+        _ => RespValue::Error("ERR Invalid OBJECT subcommand".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_switch_from_embstr_to_raw_after_44_bytes() {
+        assert_eq!(string_encoding(&"x".repeat(44)), "embstr");
+        assert_eq!(string_encoding(&"x".repeat(45)), "raw");
+        assert_eq!(string_encoding(""), "embstr");
+    }
+
+    #[test]
+    fn only_canonical_integers_are_int_encoded() {
+        for s in ["0", "12345", "-42", "9223372036854775807"] {
+            assert_eq!(string_encoding(s), "int", "{}", s);
+        }
+        for s in ["007", "+1", "-0", " 1", "9223372036854775808"] {
+            assert_eq!(string_encoding(s), "embstr", "{}", s);
+        }
+    }
+}
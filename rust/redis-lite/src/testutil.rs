@@ -0,0 +1,89 @@
+use crate::prelude::*;
+
+// Tests share the process-wide store and settings. Tests that only touch
+// their own keys hold this shared; tests that change a global setting hold it
+// exclusively so no other test observes the change.
+static GLOBAL_STATE: RwLock<()> = RwLock::new(());
+
+static HOOKS_REGISTERED: std::sync::Once = std::sync::Once::new();
+
+pub fn shared() -> std::sync::RwLockReadGuard<'static, ()> {
+    GLOBAL_STATE.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn exclusive() -> std::sync::RwLockWriteGuard<'static, ()> {
+    GLOBAL_STATE.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn bulk(s: &str) -> RespValue {
+    RespValue::BulkString(Some(s.to_string()))
+}
+
+pub fn command(args: &[&str]) -> RespValue {
+    RespValue::Array(args.iter().map(|arg| bulk(arg)).collect())
+}
+
+// Runs a command the way a client connection does, hooks included.
+pub fn run(args: &[&str]) -> RespValue {
+    HOOKS_REGISTERED.call_once(register_default_hooks);
+    let ctx = ConnCtx::new("test".to_string());
+    crate::execute(&command(args), &ctx).expect("non-empty commands always get a reply")
+}
+
+// A fresh directory per test, so persistence tests never share files.
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("redis-lite-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+thread_local! {
+    static ALLOCATIONS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    static ALLOCATED_BYTES: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    // Signed, since a thread can free memory another thread allocated.
+    static LIVE_BYTES: std::cell::Cell<i64> = const { std::cell::Cell::new(0) };
+    static PEAK_BYTES: std::cell::Cell<i64> = const { std::cell::Cell::new(0) };
+}
+
+// Counts heap allocations and bytes per thread, so allocation checks are not
+// disturbed by tests running in parallel.
+struct CountingAlloc;
+
+unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        let _ = ALLOCATED_BYTES.try_with(|n| n.set(n.get() + layout.size() as u64));
+        let _ = LIVE_BYTES.try_with(|live| {
+            live.set(live.get() + layout.size() as i64);
+            let _ = PEAK_BYTES.try_with(|peak| peak.set(peak.get().max(live.get())));
+        });
+        unsafe { std::alloc::System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        let _ = LIVE_BYTES.try_with(|live| live.set(live.get() - layout.size() as i64));
+        unsafe { std::alloc::System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+pub fn allocations() -> u64 {
+    ALLOCATIONS.with(|n| n.get())
+}
+
+// Total bytes this thread has allocated, freed or not.
+pub fn allocated_bytes() -> u64 {
+    ALLOCATED_BYTES.with(|n| n.get())
+}
+
+// Runs f and returns how far this thread's heap usage rose above where it
+// started.
+pub fn peak_bytes(f: impl FnOnce()) -> u64 {
+    let start = LIVE_BYTES.with(|live| live.get());
+    PEAK_BYTES.with(|peak| peak.set(start));
+    f();
+    (PEAK_BYTES.with(|peak| peak.get()) - start) as u64
+}